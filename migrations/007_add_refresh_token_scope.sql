@@ -0,0 +1,2 @@
+-- Scope của token family; NULL = toàn bộ scope mặc định theo role
+ALTER TABLE refresh_tokens ADD COLUMN IF NOT EXISTS scope TEXT;
@@ -0,0 +1,5 @@
+-- Keyset pagination theo (created_at, id) cần created_at luôn có giá trị
+UPDATE users SET created_at = now() WHERE created_at IS NULL;
+ALTER TABLE users ALTER COLUMN created_at SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_users_created_at_id ON users (created_at, id);
@@ -0,0 +1,7 @@
+-- Thông tin hồ sơ (PATCH /users/{id})
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS bio TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email TEXT;
+
+-- Email duy nhất, không phân biệt hoa thường
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_key ON users (lower(email));
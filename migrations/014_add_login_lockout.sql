@@ -0,0 +1,3 @@
+-- Đếm số lần login sai liên tiếp và thời điểm hết khóa tài khoản
+ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_login_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locked_until TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,4 @@
+-- Xóa mềm: user bị ẩn ngay, xóa hẳn sau thời gian ân hạn (USER_DELETE_GRACE_DAYS)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX IF NOT EXISTS idx_users_deleted_at ON users (deleted_at) WHERE deleted_at IS NOT NULL;
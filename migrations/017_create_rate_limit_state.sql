@@ -0,0 +1,9 @@
+-- Trạng thái rate limit dùng chung giữa các instance (RATE_LIMIT_BACKEND=postgres).
+-- UNLOGGED: không ghi WAL, mất dữ liệu khi crash cũng không sao (chỉ là bộ đếm).
+CREATE UNLOGGED TABLE IF NOT EXISTS rate_limit_state (
+    key TEXT PRIMARY KEY,
+    state DOUBLE PRECISION[] NOT NULL DEFAULT '{}',
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_rate_limit_state_expires_at ON rate_limit_state (expires_at);
@@ -0,0 +1,79 @@
+-- Tính một request cho key rate limit trong một câu lệnh (RATE_LIMIT_BACKEND=postgres).
+-- Dòng chỉ bị khóa trong lúc hàm chạy, không phải qua nhiều round-trip của một transaction,
+-- và thời gian luôn lấy theo đồng hồ của DB để các instance dùng chung một mốc.
+-- Phép tính giống các thuật toán trong src/rate_limit_algorithms.rs.
+CREATE OR REPLACE FUNCTION rate_limit_acquire(
+    p_key TEXT,
+    p_algorithm TEXT,
+    p_max INTEGER,
+    p_window DOUBLE PRECISION,
+    OUT allowed BOOLEAN,
+    OUT state DOUBLE PRECISION[],
+    OUT now_secs DOUBLE PRECISION
+) AS $$
+DECLARE
+    v_state DOUBLE PRECISION[];
+    v_next DOUBLE PRECISION[];
+    v_start DOUBLE PRECISION;
+    v_current DOUBLE PRECISION;
+    v_previous DOUBLE PRECISION;
+    v_tokens DOUBLE PRECISION;
+    v_tat DOUBLE PRECISION;
+BEGIN
+    now_secs := extract(epoch FROM now());
+
+    -- Upsert khóa dòng tới hết hàm; state đã hết hạn được coi như rỗng
+    INSERT INTO rate_limit_state AS r (key, expires_at) VALUES (p_key, now())
+    ON CONFLICT (key) DO UPDATE
+        SET state = CASE WHEN r.expires_at <= now() THEN '{}' ELSE r.state END
+    RETURNING r.state INTO v_state;
+
+    -- v_next = state mới nếu request được chấp nhận, NULL nếu vượt quota
+    CASE p_algorithm
+    WHEN 'sliding_log' THEN
+        v_state := ARRAY(SELECT t FROM unnest(v_state) AS t WHERE t > now_secs - p_window ORDER BY t);
+        IF cardinality(v_state) < p_max THEN
+            v_next := v_state || now_secs;
+        END IF;
+    WHEN 'sliding_window' THEN
+        v_start := floor(now_secs / p_window) * p_window;
+        IF cardinality(v_state) = 3 AND v_state[1] = v_start THEN
+            v_current := v_state[2];
+            v_previous := v_state[3];
+        ELSIF cardinality(v_state) = 3 AND v_state[1] = v_start - p_window THEN
+            v_current := 0;
+            v_previous := v_state[2];
+        ELSE
+            v_current := 0;
+            v_previous := 0;
+        END IF;
+        IF v_previous * (1 - (now_secs - v_start) / p_window) + v_current + 1 <= p_max THEN
+            v_next := ARRAY[v_start, v_current + 1, v_previous];
+        END IF;
+    WHEN 'token_bucket' THEN
+        IF cardinality(v_state) = 2 THEN
+            v_tokens := least(v_state[1] + greatest(now_secs - v_state[2], 0) * p_max / p_window, p_max);
+        ELSE
+            v_tokens := p_max;
+        END IF;
+        IF v_tokens >= 1 THEN
+            v_next := ARRAY[v_tokens - 1, now_secs];
+        END IF;
+    WHEN 'gcra' THEN
+        v_tat := greatest(coalesce(v_state[1], now_secs), now_secs) + p_window / p_max;
+        IF now_secs >= v_tat - p_window THEN
+            v_next := ARRAY[v_tat];
+        END IF;
+    ELSE
+        RAISE EXCEPTION 'unknown rate limit algorithm %', p_algorithm;
+    END CASE;
+
+    allowed := v_next IS NOT NULL;
+    state := coalesce(v_next, v_state);
+    -- State hết hạn sau 2 window: đủ cho mọi thuật toán (sliding window cần cả window trước)
+    UPDATE rate_limit_state
+    SET state = rate_limit_acquire.state,
+        expires_at = now() + make_interval(secs => 2 * p_window)
+    WHERE key = p_key;
+END;
+$$ LANGUAGE plpgsql;
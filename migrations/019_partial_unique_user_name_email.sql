@@ -0,0 +1,7 @@
+-- Tên và email chỉ cần duy nhất giữa các user chưa bị xóa mềm: xóa tài khoản xong có thể đăng ký lại
+-- ngay mà không lộ rằng tài khoản cũ vẫn còn trong thời gian ân hạn
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_name_key;
+CREATE UNIQUE INDEX IF NOT EXISTS users_name_key ON users (name) WHERE deleted_at IS NULL;
+
+DROP INDEX IF EXISTS users_email_key;
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_key ON users (lower(email)) WHERE deleted_at IS NULL;
@@ -0,0 +1,113 @@
+use chrono::{Duration, Utc};
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use sqlx::PgPool;
+
+use crate::db;
+use crate::errors::ApiError;
+use crate::jwt::{self, Claims};
+use crate::scopes;
+
+/// Tiền tố nhận diện API key: `lsk_<prefix>_<secret>`
+const KEY_PREFIX: &str = "lsk_";
+
+/// Thời gian cache kết quả xác thực key (giây) để không phải chạy Argon2 mỗi request.
+/// Cache hit vẫn kiểm tra `revoked_at` trong DB, nên key bị thu hồi ở instance khác bị chặn ngay.
+const CACHE_TTL_SECS: i64 = 30;
+
+lazy_static! {
+    /// SHA-256 của key -> (claims đã xác thực, id của key, thời điểm kiểm tra)
+    static ref VERIFIED: DashMap<String, (Claims, i32, i64)> = DashMap::new();
+}
+
+/// Sinh API key mới, trả về (prefix, secret, key đầy đủ). Chỉ hash của secret được lưu DB.
+pub fn generate() -> (String, String, String) {
+    let prefix = jwt::generate_opaque_token(8);
+    let secret = jwt::generate_opaque_token(32);
+    let key = format!("{}{}_{}", KEY_PREFIX, prefix, secret);
+    (prefix, secret, key)
+}
+
+/// Tách `lsk_<prefix>_<secret>` thành (prefix, secret), chưa kiểm tra gì với DB
+fn split(key: &str) -> Option<(&str, &str)> {
+    key.strip_prefix(KEY_PREFIX).and_then(|rest| rest.split_once('_'))
+}
+
+/// Xác thực API key và dựng `Claims` giống access token
+pub async fn authenticate(pool: &PgPool, key: &str) -> Result<Claims, ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid API key".into());
+    let now = Utc::now().timestamp();
+    let cache_key = jwt::hash_refresh_token(key);
+
+    let cached = VERIFIED
+        .get(&cache_key)
+        .filter(|entry| now - entry.2 < CACHE_TTL_SECS)
+        .map(|entry| (entry.0.clone(), entry.1));
+    if let Some((claims, key_id)) = cached {
+        if db::is_api_key_active(pool, key_id).await? {
+            return Ok(claims);
+        }
+        VERIFIED.remove(&cache_key);
+        return Err(ApiError::Unauthorized("API key revoked".into()));
+    }
+
+    let (prefix, secret) = split(key).ok_or_else(invalid)?;
+
+    let api_key = db::get_api_key_by_prefix(pool, prefix)
+        .await?
+        .ok_or_else(invalid)?;
+    if api_key.revoked_at.is_some() {
+        return Err(ApiError::Unauthorized("API key revoked".into()));
+    }
+    let valid = db::verify_password(&api_key.key_hash, secret)
+        .map_err(|e| ApiError::InternalError(format!("API key verify error: {}", e)))?;
+    if !valid {
+        return Err(invalid());
+    }
+
+    // Role hiện tại của user giới hạn scope của key
+    let user = db::get_user_by_id(pool, api_key.user_id)
+        .await?
+        .ok_or_else(invalid)?;
+    db::touch_api_key(pool, api_key.id).await?;
+
+    let claims = Claims {
+        sub: user.id,
+        name: user.name,
+        role: user.role,
+        scope: scopes::grant(user.role, Some(&api_key.scope)),
+        exp: (Utc::now() + Duration::seconds(CACHE_TTL_SECS)).timestamp() as usize,
+        iat: now as usize,
+        jti: jwt::generate_opaque_token(16),
+        sid: format!("apikey:{}", api_key.id),
+    };
+
+    purge_expired();
+    VERIFIED.insert(cache_key, (claims.clone(), api_key.id, now));
+    Ok(claims)
+}
+
+/// (user_id, key_id) của key đã được xác thực gần đây; chỉ đọc cache, không chạy Argon2 hay hỏi DB
+pub fn cached_identity(key: &str) -> Option<(i32, i32)> {
+    let now = Utc::now().timestamp();
+    VERIFIED
+        .get(&jwt::hash_refresh_token(key))
+        .filter(|entry| now - entry.2 < CACHE_TTL_SECS)
+        .map(|entry| (entry.0.sub, entry.1))
+}
+
+/// Bỏ cache của key đã bị thu hồi
+pub fn forget(key_id: i32) {
+    VERIFIED.retain(|_, (_, id, _)| *id != key_id);
+}
+
+/// Bỏ cache mọi key của user (đổi role, xóa user)
+pub fn forget_user(user_id: i32) {
+    VERIFIED.retain(|_, (claims, _, _)| claims.sub != user_id);
+}
+
+/// Dọn các entry đã hết hạn khỏi cache
+fn purge_expired() {
+    let now = Utc::now().timestamp();
+    VERIFIED.retain(|_, (_, _, checked_at)| now - *checked_at < CACHE_TTL_SECS);
+}
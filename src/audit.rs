@@ -0,0 +1,79 @@
+use std::net::IpAddr;
+
+use sqlx::PgPool;
+
+use crate::db;
+
+// Các loại sự kiện ghi vào audit_events
+pub const USER_REGISTER: &str = "user.register";
+pub const USER_DELETE: &str = "user.delete";
+pub const AVATAR_UPDATE: &str = "user.avatar_update";
+pub const LOGIN: &str = "auth.login";
+pub const LOGIN_FAILED: &str = "auth.login_failed";
+pub const MFA_CHALLENGE: &str = "auth.mfa_challenge";
+
+/// Thông tin client gắn vào sự kiện
+#[derive(Debug, Clone, Default)]
+pub struct Client {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl Client {
+    pub fn new(client_ip: Option<IpAddr>, user_agent: Option<String>) -> Self {
+        Client {
+            ip: client_ip.map(|ip| ip.to_string()),
+            user_agent,
+        }
+    }
+}
+
+/// Ghi một sự kiện ở nền: lỗi ghi log chỉ được cảnh báo, không làm hỏng request
+pub fn record(
+    pool: &PgPool,
+    action: &'static str,
+    actor_id: Option<i32>,
+    target_user_id: Option<i32>,
+    client: &Client,
+    metadata: serde_json::Value,
+) {
+    let pool = pool.clone();
+    let client = client.clone();
+    tokio::spawn(async move { insert(&pool, action, actor_id, target_user_id, &client, &metadata).await });
+}
+
+/// Ghi sự kiện và chờ ghi xong trước khi trả response (login thất bại, xóa user):
+/// sự kiện không bị mất khi server dừng ngay sau đó. Lỗi ghi log vẫn chỉ được cảnh báo.
+pub async fn record_now(
+    pool: &PgPool,
+    action: &'static str,
+    actor_id: Option<i32>,
+    target_user_id: Option<i32>,
+    client: &Client,
+    metadata: serde_json::Value,
+) {
+    insert(pool, action, actor_id, target_user_id, client, &metadata).await
+}
+
+async fn insert(
+    pool: &PgPool,
+    action: &'static str,
+    actor_id: Option<i32>,
+    target_user_id: Option<i32>,
+    client: &Client,
+    metadata: &serde_json::Value,
+) {
+    if let Err(e) = db::insert_audit_event(
+        pool,
+        actor_id,
+        target_user_id,
+        action,
+        client.ip.as_deref(),
+        client.user_agent.as_deref(),
+        metadata,
+    )
+    .await
+    {
+        tracing::warn!("Audit event {} not recorded: {}", action, e);
+    }
+}
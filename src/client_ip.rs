@@ -0,0 +1,345 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use warp::http::HeaderMap;
+use warp::Filter;
+
+use crate::config::CONFIG;
+
+/// Một dải địa chỉ dạng CIDR, ví dụ `10.0.0.0/8` hoặc `::1/128` (không có `/` = một địa chỉ)
+#[derive(Debug, Clone, Copy)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = addr.trim().parse().map_err(|_| format!("invalid address '{}'", addr))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.trim().parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(|| format!("invalid prefix '{}'", p))?,
+            None => max,
+        };
+        // `::ffff:a.b.c.d/n` (n >= 96) là dải IPv4 với prefix n - 96
+        match network.to_canonical() {
+            IpAddr::V4(v4) if network.is_ipv6() && prefix >= 96 => Ok(Cidr { network: IpAddr::V4(v4), prefix: prefix - 96 }),
+            _ => Ok(Cidr { network, prefix }),
+        }
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Header do reverse proxy ghi IP client. Chỉ đọc đúng một header đã cấu hình: proxy thường
+/// chuyển tiếp nguyên vẹn các header khác do client tự gửi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyHeader {
+    XForwardedFor,
+    XRealIp,
+    Forwarded,
+}
+
+impl FromStr for ProxyHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "x-forwarded-for" => Ok(ProxyHeader::XForwardedFor),
+            "x-real-ip" => Ok(ProxyHeader::XRealIp),
+            "forwarded" => Ok(ProxyHeader::Forwarded),
+            other => Err(format!(
+                "unknown header '{}' (expected x-forwarded-for, x-real-ip or forwarded)",
+                other
+            )),
+        }
+    }
+}
+
+/// Danh sách proxy tin cậy và header mà chúng ghi IP client
+#[derive(Debug, Clone)]
+pub struct TrustedProxies {
+    proxies: Vec<Cidr>,
+    header: ProxyHeader,
+}
+
+/// Cấu hình proxy, nạp một lần từ TRUSTED_PROXIES / TRUSTED_PROXY_HEADER
+static TRUSTED_PROXIES: OnceLock<TrustedProxies> = OnceLock::new();
+
+/// Nạp danh sách proxy tin cậy khi khởi động (gọi một lần trong main)
+pub fn init() -> anyhow::Result<()> {
+    let proxies = CONFIG
+        .trusted_proxies
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| p.parse::<Cidr>().map_err(|e| anyhow::anyhow!("Invalid TRUSTED_PROXIES entry '{}': {}", p, e)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let header = CONFIG
+        .trusted_proxy_header
+        .parse::<ProxyHeader>()
+        .map_err(|e| anyhow::anyhow!("Invalid TRUSTED_PROXY_HEADER: {}", e))?;
+    if !proxies.is_empty() {
+        tracing::info!("Trusting {:?} from {} proxy ranges", header, proxies.len());
+    }
+    TRUSTED_PROXIES
+        .set(TrustedProxies { proxies, header })
+        .map_err(|_| anyhow::anyhow!("Trusted proxies already loaded"))
+}
+
+/// Parse một node trong header: `1.2.3.4`, `1.2.3.4:5678`, `[::1]`, `[::1]:5678`, có thể nằm trong ngoặc kép
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip.to_canonical());
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip().to_canonical());
+    }
+    node.strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .and_then(|(ip, _)| ip.parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+}
+
+/// Các hop `for=` của header Forwarded (RFC 7239), theo thứ tự client → proxy gần nhất
+fn forwarded_hops(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                key.trim().eq_ignore_ascii_case("for").then(|| value.trim().to_string())
+            })
+        })
+        .collect()
+}
+
+/// Các hop của một header dạng danh sách (X-Forwarded-For, X-Real-IP), theo thứ tự client → proxy gần nhất
+fn list_hops(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|hop| hop.trim().to_string())
+        .filter(|hop| !hop.is_empty())
+        .collect()
+}
+
+impl TrustedProxies {
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// IP thật của client. Header chỉ được dùng khi peer là proxy tin cậy; chuỗi hop được duyệt
+    /// từ phải sang trái và bỏ qua các proxy tin cậy, hop đầu tiên không tin cậy là client.
+    pub fn resolve(&self, peer: Option<SocketAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer = peer?.ip().to_canonical();
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+
+        let hops = match self.header {
+            ProxyHeader::XForwardedFor => list_hops(headers, "x-forwarded-for"),
+            ProxyHeader::XRealIp => list_hops(headers, "x-real-ip"),
+            ProxyHeader::Forwarded => forwarded_hops(headers),
+        };
+
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            // Hop không đọc được (vd. "unknown", tên ẩn danh) → dừng ở proxy tin cậy gần nhất
+            let Some(ip) = parse_node(hop) else { break };
+            client = ip;
+            if !self.is_trusted(ip) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Warp filter trả về IP thật của client (dùng cho rate limit, audit log, session)
+pub fn client_ip() -> impl Filter<Extract = (Option<IpAddr>,), Error = std::convert::Infallible> + Clone {
+    warp::addr::remote()
+        .and(warp::header::headers_cloned())
+        .map(|peer: Option<SocketAddr>, headers: HeaderMap| match TRUSTED_PROXIES.get() {
+            Some(trusted) => trusted.resolve(peer, &headers),
+            None => peer.map(|p| p.ip().to_canonical()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::HeaderValue;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn peer(s: &str) -> Option<SocketAddr> {
+        Some(SocketAddr::new(ip(s), 40000))
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    fn trusted(header: ProxyHeader) -> TrustedProxies {
+        TrustedProxies { proxies: vec![cidr("10.0.0.0/8"), cidr("::1/128")], header }
+    }
+
+    #[test]
+    fn cidr_contains_v4() {
+        let net = cidr("192.168.1.0/24");
+        assert!(net.contains(ip("192.168.1.0")));
+        assert!(net.contains(ip("192.168.1.255")));
+        assert!(!net.contains(ip("192.168.2.1")));
+        assert!(cidr("0.0.0.0/0").contains(ip("8.8.8.8")));
+        assert!(cidr("10.1.2.3").contains(ip("10.1.2.3")));
+        assert!(!cidr("10.1.2.3").contains(ip("10.1.2.4")));
+    }
+
+    #[test]
+    fn cidr_contains_v6() {
+        let net = cidr("2001:db8::/32");
+        assert!(net.contains(ip("2001:db8::1")));
+        assert!(net.contains(ip("2001:db8:ffff::1")));
+        assert!(!net.contains(ip("2001:db9::1")));
+        assert!(cidr("::/0").contains(ip("fe80::1")));
+        assert!(!net.contains(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn cidr_contains_v4_mapped() {
+        assert!(cidr("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+        assert!(cidr("::ffff:10.0.0.1").contains(ip("10.0.0.1")));
+        assert!(!cidr("10.0.0.0/8").contains(ip("::ffff:11.0.0.1")));
+        assert!(cidr("::ffff:10.0.0.0/104").contains(ip("10.200.0.1")));
+        assert!(!cidr("::ffff:10.0.0.0/104").contains(ip("11.0.0.1")));
+    }
+
+    #[test]
+    fn cidr_rejects_invalid() {
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("::/129".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/x".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn parse_node_formats() {
+        assert_eq!(parse_node("1.2.3.4"), Some(ip("1.2.3.4")));
+        assert_eq!(parse_node(" 1.2.3.4:5678 "), Some(ip("1.2.3.4")));
+        assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("[2001:db8::1]"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("\"[2001:db8::1]:4711\""), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("::ffff:1.2.3.4"), Some(ip("1.2.3.4")));
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+        assert_eq!(parse_node(""), None);
+    }
+
+    #[test]
+    fn untrusted_peer_ignores_headers() {
+        let h = headers(&[("x-forwarded-for", "9.9.9.9")]);
+        assert_eq!(trusted(ProxyHeader::XForwardedFor).resolve(peer("8.8.8.8"), &h), Some(ip("8.8.8.8")));
+    }
+
+    #[test]
+    fn rightmost_untrusted_hop_is_client() {
+        let t = trusted(ProxyHeader::XForwardedFor);
+        // Client tự gửi "1.1.1.1", proxy nối thêm IP thật 9.9.9.9 và proxy nội bộ 10.0.0.2
+        let h = headers(&[("x-forwarded-for", "1.1.1.1, 9.9.9.9, 10.0.0.2")]);
+        assert_eq!(t.resolve(peer("10.0.0.1"), &h), Some(ip("9.9.9.9")));
+
+        // Nhiều dòng header được nối theo thứ tự
+        let h = headers(&[("x-forwarded-for", "1.1.1.1"), ("x-forwarded-for", "9.9.9.9")]);
+        assert_eq!(t.resolve(peer("10.0.0.1"), &h), Some(ip("9.9.9.9")));
+    }
+
+    #[test]
+    fn all_trusted_hops_resolve_to_leftmost() {
+        let h = headers(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(trusted(ProxyHeader::XForwardedFor).resolve(peer("10.0.0.1"), &h), Some(ip("10.0.0.3")));
+    }
+
+    #[test]
+    fn unparsable_hop_stops_at_last_valid() {
+        let h = headers(&[("x-forwarded-for", "9.9.9.9, garbage, 10.0.0.2")]);
+        assert_eq!(trusted(ProxyHeader::XForwardedFor).resolve(peer("10.0.0.1"), &h), Some(ip("10.0.0.2")));
+    }
+
+    #[test]
+    fn missing_header_falls_back_to_peer() {
+        assert_eq!(trusted(ProxyHeader::XForwardedFor).resolve(peer("10.0.0.1"), &HeaderMap::new()), Some(ip("10.0.0.1")));
+        assert_eq!(trusted(ProxyHeader::XForwardedFor).resolve(None, &HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn only_configured_header_is_read() {
+        // Proxy ghi X-Forwarded-For nhưng chuyển tiếp nguyên Forwarded / X-Real-IP do client gửi
+        let spoofed = headers(&[
+            ("forwarded", "for=6.6.6.6"),
+            ("x-real-ip", "7.7.7.7"),
+            ("x-forwarded-for", "9.9.9.9"),
+        ]);
+        assert_eq!(trusted(ProxyHeader::XForwardedFor).resolve(peer("10.0.0.1"), &spoofed), Some(ip("9.9.9.9")));
+
+        // Không có header đã cấu hình → không dùng header khác, lấy IP của proxy
+        let only_spoofed = headers(&[("forwarded", "for=6.6.6.6"), ("x-real-ip", "7.7.7.7")]);
+        assert_eq!(trusted(ProxyHeader::XForwardedFor).resolve(peer("10.0.0.1"), &only_spoofed), Some(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn forwarded_and_real_ip_headers() {
+        let h = headers(&[("forwarded", "for=1.1.1.1, for=\"[2001:db8::1]:4711\";proto=https, for=10.0.0.2")]);
+        assert_eq!(trusted(ProxyHeader::Forwarded).resolve(peer("::1"), &h), Some(ip("2001:db8::1")));
+
+        let h = headers(&[("x-real-ip", "9.9.9.9"), ("x-forwarded-for", "6.6.6.6")]);
+        assert_eq!(trusted(ProxyHeader::XRealIp).resolve(peer("10.0.0.1"), &h), Some(ip("9.9.9.9")));
+    }
+
+    #[test]
+    fn proxy_header_names() {
+        assert_eq!("X-Forwarded-For".parse::<ProxyHeader>(), Ok(ProxyHeader::XForwardedFor));
+        assert_eq!("x-real-ip".parse::<ProxyHeader>(), Ok(ProxyHeader::XRealIp));
+        assert_eq!("forwarded".parse::<ProxyHeader>(), Ok(ProxyHeader::Forwarded));
+        assert!("x-client-ip".parse::<ProxyHeader>().is_err());
+    }
+}
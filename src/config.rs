@@ -0,0 +1,127 @@
+use std::env;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+
+use crate::password_policy::PasswordPolicy;
+
+/// Cấu hình đọc từ biến môi trường (.env)
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Session timeout khi không hoạt động (giây)
+    pub session_timeout_secs: i64,
+    /// Backend lưu session: "postgres" (mặc định) hoặc "memory"
+    pub session_store: String,
+    /// Thư mục chứa các khóa ký `<kid>.pem` (hỗ trợ xoay vòng khóa)
+    pub jwt_keys_dir: Option<String>,
+    /// Chu kỳ nạp lại JWT_KEYS_DIR (giây); khóa mới chỉ dùng để ký sau khoảng này
+    pub jwt_keys_reload_secs: u64,
+    /// Private key PEM (RS256 hoặc EdDSA) dùng ký JWT
+    pub jwt_private_key_path: Option<String>,
+    /// `kid` của khóa ký (mặc định: JWK thumbprint)
+    pub jwt_key_id: Option<String>,
+    /// Secret HS256 (chỉ dùng khi không có JWT_PRIVATE_KEY_PATH)
+    pub jwt_secret: Option<String>,
+    /// Backend gửi email: "stdout" (mặc định) hoặc "file"
+    pub mailer: String,
+    /// File ghi email khi MAILER=file
+    pub mailer_file_path: String,
+    /// URL gốc của ứng dụng, dùng để dựng link trong email
+    pub app_base_url: String,
+    /// Thời hạn của token đặt lại mật khẩu (phút)
+    pub password_reset_ttl_mins: i64,
+    /// Thời hạn của mã xác thực email (giờ)
+    pub email_verification_ttl_hours: i64,
+    /// Từ chối login khi email chưa được xác thực
+    pub require_verified_email: bool,
+    /// Tên issuer hiển thị trong app authenticator (TOTP)
+    pub totp_issuer: String,
+    /// Số lần login sai liên tiếp trước khi khóa tài khoản
+    pub login_max_failures: i32,
+    /// Thời gian khóa lần đầu (giây), nhân đôi sau mỗi lần sai tiếp theo
+    pub login_lockout_base_secs: i64,
+    /// Thời gian khóa tối đa (giây)
+    pub login_lockout_max_secs: i64,
+    /// Quy tắc mật khẩu (PASSWORD_*)
+    pub password_policy: PasswordPolicy,
+    /// Tham số Argon2id cho hash mật khẩu mới (hash cũ yếu hơn được rehash khi login)
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    /// Thời gian ân hạn (ngày) trước khi user đã xóa bị xóa hẳn; trong thời gian này admin có thể khôi phục
+    pub user_delete_grace_days: i64,
+    /// Policy rate limit bổ sung / ghi đè mặc định:
+    /// `name=<max>/<window_secs>[:ip|user|api_key[:sliding_log|sliding_window|token_bucket|gcra]],...`
+    pub rate_limit_policies: Option<String>,
+    /// Backend lưu state rate limit: "memory" (mặc định) hoặc "postgres" (dùng chung giữa các instance)
+    pub rate_limit_backend: String,
+    /// Thời gian chờ backend rate limit tối đa (ms); quá hạn hoặc backend lỗi thì cho request đi qua (có log cảnh báo)
+    pub rate_limit_backend_timeout_ms: u64,
+    /// CIDR của các reverse proxy tin cậy (phân cách bằng dấu phẩy); chỉ khi request đến từ đây
+    /// mới đọc IP client từ TRUSTED_PROXY_HEADER
+    pub trusted_proxies: Option<String>,
+    /// Header mà proxy tin cậy ghi IP client: "x-forwarded-for" (mặc định), "x-real-ip" hoặc "forwarded"
+    pub trusted_proxy_header: String,
+    /// Số key rate limit tối đa giữ trong bộ nhớ; vượt quá thì bỏ key ít dùng gần đây nhất
+    pub rate_limit_max_keys: usize,
+    /// Chu kỳ dọn key rate limit không còn hoạt động (giây)
+    pub rate_limit_sweep_secs: u64,
+}
+
+impl Config {
+    /// Thời gian ân hạn của user đã xóa (giây)
+    pub fn user_delete_grace_secs(&self) -> i64 {
+        self.user_delete_grace_days * 24 * 60 * 60
+    }
+
+    pub fn from_env() -> Self {
+        Config {
+            session_timeout_secs: env_or("SESSION_TIMEOUT_SECS", 30 * 60),
+            session_store: env_or("SESSION_STORE", "postgres".to_string()),
+            jwt_keys_dir: env_opt("JWT_KEYS_DIR"),
+            jwt_keys_reload_secs: env_or("JWT_KEYS_RELOAD_SECS", 60),
+            jwt_private_key_path: env_opt("JWT_PRIVATE_KEY_PATH"),
+            jwt_key_id: env_opt("JWT_KEY_ID"),
+            jwt_secret: env_opt("JWT_SECRET"),
+            mailer: env_or("MAILER", "stdout".to_string()),
+            mailer_file_path: env_or("MAILER_FILE_PATH", "mail.log".to_string()),
+            app_base_url: env_or("APP_BASE_URL", "http://127.0.0.1:3030".to_string()),
+            password_reset_ttl_mins: env_or("PASSWORD_RESET_TTL_MINS", 30),
+            email_verification_ttl_hours: env_or("EMAIL_VERIFICATION_TTL_HOURS", 24),
+            require_verified_email: env_or("REQUIRE_VERIFIED_EMAIL", false),
+            totp_issuer: env_or("TOTP_ISSUER", "LocalServerAPI".to_string()),
+            login_max_failures: env_or("LOGIN_MAX_FAILURES", 5),
+            login_lockout_base_secs: env_or("LOGIN_LOCKOUT_BASE_SECS", 60),
+            login_lockout_max_secs: env_or("LOGIN_LOCKOUT_MAX_SECS", 60 * 60),
+            password_policy: PasswordPolicy::from_env(),
+            argon2_memory_kib: env_or("ARGON2_MEMORY_KIB", 4096),
+            argon2_iterations: env_or("ARGON2_ITERATIONS", 3),
+            argon2_parallelism: env_or("ARGON2_PARALLELISM", 1),
+            user_delete_grace_days: env_or("USER_DELETE_GRACE_DAYS", 30),
+            rate_limit_policies: env_opt("RATE_LIMIT_POLICIES"),
+            rate_limit_backend: env_or("RATE_LIMIT_BACKEND", "memory".to_string()),
+            rate_limit_backend_timeout_ms: env_or("RATE_LIMIT_BACKEND_TIMEOUT_MS", 50),
+            trusted_proxies: env_opt("TRUSTED_PROXIES"),
+            trusted_proxy_header: env_or("TRUSTED_PROXY_HEADER", "x-forwarded-for".to_string()),
+            rate_limit_max_keys: env_or("RATE_LIMIT_MAX_KEYS", 100_000),
+            rate_limit_sweep_secs: env_or("RATE_LIMIT_SWEEP_SECS", 60),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref CONFIG: Config = Config::from_env();
+}
+
+/// Đọc biến môi trường và parse, dùng giá trị mặc định nếu thiếu hoặc sai định dạng
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Đọc biến môi trường tùy chọn (bỏ qua giá trị rỗng)
+pub fn env_opt(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
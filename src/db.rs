@@ -1,22 +1,31 @@
 use sqlx::PgPool;
-use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, PasswordVerifier, Version};
 use password_hash::SaltString;
 use rand_core::OsRng;
 use anyhow::Result;
+use lazy_static::lazy_static;
 use chrono::{DateTime, Utc};
+use crate::config::CONFIG;
 use crate::errors::ApiError;
+use crate::models::{ApiKey, AuditEvent, Role, Session, User, UserCursor, UserSort};
 
-/// Hash mật khẩu bằng Argon2
+/// Tham số Argon2 hiện tại (ARGON2_MEMORY_KIB / ARGON2_ITERATIONS / ARGON2_PARALLELISM)
+pub fn argon2_params() -> Result<Params> {
+    Params::new(CONFIG.argon2_memory_kib, CONFIG.argon2_iterations, CONFIG.argon2_parallelism, None)
+        .map_err(|e| anyhow::anyhow!("Invalid Argon2 params: {}", e))
+}
+
+/// Hash mật khẩu bằng Argon2id với tham số trong config
 pub fn hash_password(password: &str) -> Result<String> {
     let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params()?);
     let password_hash = argon2
         .hash_password(password.as_bytes(), &salt)
         .map_err(|e| anyhow::anyhow!(e))?;
     Ok(password_hash.to_string())
 }
 
-/// Xác thực mật khẩu với hash
+/// Xác thực mật khẩu với hash (dùng tham số lưu trong hash)
 pub fn verify_password(hash: &str, password: &str) -> Result<bool> {
     let parsed = password_hash::PasswordHash::new(hash)
         .map_err(|e| anyhow::anyhow!(e))?;
@@ -24,12 +33,42 @@ pub fn verify_password(hash: &str, password: &str) -> Result<bool> {
     Ok(argon2.verify_password(password.as_bytes(), &parsed).is_ok())
 }
 
+/// Hash có yếu hơn tham số hiện tại không (thuật toán khác hoặc cost thấp hơn)
+pub fn needs_rehash(hash: &str) -> bool {
+    let Ok(current) = argon2_params() else {
+        return false;
+    };
+    let Ok(parsed) = password_hash::PasswordHash::new(hash) else {
+        return true;
+    };
+    if parsed.algorithm != Algorithm::Argon2id.ident() {
+        return true;
+    }
+    match Params::try_from(&parsed) {
+        Ok(p) => {
+            p.m_cost() < current.m_cost() || p.t_cost() < current.t_cost() || p.p_cost() < current.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+lazy_static! {
+    /// Hash giả dùng khi user không tồn tại (cùng tham số Argon2 với hash thật)
+    static ref DUMMY_HASH: String = hash_password("dummy-password").expect("dummy hash");
+}
+
+/// Verify với hash giả để thời gian phản hồi không lộ user có tồn tại hay không
+pub fn verify_dummy_password(password: &str) {
+    let _ = verify_password(&DUMMY_HASH, password);
+}
+
 /// Tạo user mới trong DB
-pub async fn create_user(pool: &PgPool, name: &str, hash: &str) -> Result<i32, ApiError> {
+pub async fn create_user(pool: &PgPool, name: &str, hash: &str, email: Option<&str>) -> Result<i32, ApiError> {
     let res = sqlx::query!(
-        r#"INSERT INTO users (name, password_hash) VALUES ($1, $2) RETURNING id"#,
+        r#"INSERT INTO users (name, password_hash, email) VALUES ($1, $2, $3) RETURNING id"#,
         name,
-        hash
+        hash,
+        email
     )
     .fetch_one(pool)
     .await;
@@ -37,7 +76,9 @@ pub async fn create_user(pool: &PgPool, name: &str, hash: &str) -> Result<i32, A
     match res {
         Ok(rec) => Ok(rec.id),
         Err(sqlx::Error::Database(db_err)) => {
-            if db_err.code() == Some("23505".into()) {
+            if db_err.code() == Some("23505".into()) && db_err.constraint() == Some("users_email_key") {
+                Err(ApiError::BadRequest("Email already in use".into()))
+            } else if db_err.code() == Some("23505".into()) {
                 Err(ApiError::UserExists)
             } else {
                 Err(ApiError::InternalError(format!("Database error: {}", db_err)))
@@ -47,38 +88,342 @@ pub async fn create_user(pool: &PgPool, name: &str, hash: &str) -> Result<i32, A
     }
 }
 
+// Một dòng của bảng users như trong DB (role dạng text)
+struct UserRow {
+    id: i32,
+    name: String,
+    password_hash: String,
+    role: String,
+    created_at: DateTime<Utc>,
+    display_name: Option<String>,
+    bio: Option<String>,
+    email: Option<String>,
+    email_verified_at: Option<DateTime<Utc>>,
+    avatar_path: Option<String>,
+    totp_secret: Option<String>,
+    totp_enabled_at: Option<DateTime<Utc>>,
+    totp_last_step: Option<i64>,
+}
+
+impl TryFrom<UserRow> for User {
+    type Error = ApiError;
+
+    fn try_from(r: UserRow) -> Result<Self, Self::Error> {
+        Ok(User {
+            id: r.id,
+            name: r.name,
+            password_hash: r.password_hash,
+            role: r.role.parse().map_err(ApiError::InternalError)?,
+            created_at: r.created_at,
+            display_name: r.display_name,
+            bio: r.bio,
+            email: r.email,
+            email_verified_at: r.email_verified_at,
+            avatar_path: r.avatar_path,
+            totp_secret: r.totp_secret,
+            totp_enabled_at: r.totp_enabled_at,
+            totp_last_step: r.totp_last_step,
+        })
+    }
+}
+
 /// Lấy user theo tên
-pub async fn get_user_by_name(
-    pool: &PgPool,
-    name: &str,
-) -> Result<Option<(i32, String, String, DateTime<Utc>)>, ApiError> {
-    let rec = sqlx::query!(
-        r#"SELECT id, name, password_hash, created_at FROM users WHERE name = $1"#,
+pub async fn get_user_by_name(pool: &PgPool, name: &str) -> Result<Option<User>, ApiError> {
+    let rec = sqlx::query_as!(
+        UserRow,
+        r#"SELECT id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step FROM users WHERE name = $1 AND deleted_at IS NULL"#,
         name
     )
     .fetch_optional(pool)
     .await
     .map_err(|e| ApiError::InternalError(format!("DB fetch error: {}", e)))?;
 
-    Ok(rec.map(|r| {
-        let created_at = r.created_at.unwrap_or(Utc::now()); // unwrap Option
-        (r.id, r.name, r.password_hash, created_at)
-    }))
+    rec.map(User::try_from).transpose()
+}
+
+/// Lấy user theo ID
+pub async fn get_user_by_id(pool: &PgPool, id: i32) -> Result<Option<User>, ApiError> {
+    let rec = sqlx::query_as!(
+        UserRow,
+        r#"SELECT id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step FROM users WHERE id = $1 AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch error: {}", e)))?;
+
+    rec.map(User::try_from).transpose()
+}
+
+/// Danh sách user (keyset pagination: chỉ lấy các dòng sau `cursor` theo thứ tự `sort`)
+pub async fn list_users(
+    pool: &PgPool,
+    name_prefix: Option<&str>,
+    created_after: Option<DateTime<Utc>>,
+    sort: UserSort,
+    cursor: Option<&UserCursor>,
+    limit: i64,
+) -> Result<Vec<User>, ApiError> {
+    let after_id = cursor.map(|c| c.id);
+    let after_created = cursor.map(|c| c.created_at);
+
+    let rows = match sort {
+        UserSort::IdAsc => sqlx::query_as!(
+            UserRow,
+            r#"SELECT id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step FROM users
+               WHERE deleted_at IS NULL
+                 AND ($1::text IS NULL OR starts_with(name, $1))
+                 AND ($2::timestamptz IS NULL OR created_at > $2)
+                 AND ($3::int IS NULL OR id > $3)
+               ORDER BY id
+               LIMIT $4"#,
+            name_prefix,
+            created_after,
+            after_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await,
+        UserSort::IdDesc => sqlx::query_as!(
+            UserRow,
+            r#"SELECT id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step FROM users
+               WHERE deleted_at IS NULL
+                 AND ($1::text IS NULL OR starts_with(name, $1))
+                 AND ($2::timestamptz IS NULL OR created_at > $2)
+                 AND ($3::int IS NULL OR id < $3)
+               ORDER BY id DESC
+               LIMIT $4"#,
+            name_prefix,
+            created_after,
+            after_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await,
+        UserSort::CreatedAsc => sqlx::query_as!(
+            UserRow,
+            r#"SELECT id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step FROM users
+               WHERE deleted_at IS NULL
+                 AND ($1::text IS NULL OR starts_with(name, $1))
+                 AND ($2::timestamptz IS NULL OR created_at > $2)
+                 AND ($3::timestamptz IS NULL OR (created_at, id) > ($3, $4::int))
+               ORDER BY created_at, id
+               LIMIT $5"#,
+            name_prefix,
+            created_after,
+            after_created,
+            after_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await,
+        UserSort::CreatedDesc => sqlx::query_as!(
+            UserRow,
+            r#"SELECT id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step FROM users
+               WHERE deleted_at IS NULL
+                 AND ($1::text IS NULL OR starts_with(name, $1))
+                 AND ($2::timestamptz IS NULL OR created_at > $2)
+                 AND ($3::timestamptz IS NULL OR (created_at, id) < ($3, $4::int))
+               ORDER BY created_at DESC, id DESC
+               LIMIT $5"#,
+            name_prefix,
+            created_after,
+            after_created,
+            after_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await,
+    }
+    .map_err(|e| ApiError::InternalError(format!("DB list users error: {}", e)))?;
+
+    rows.into_iter().map(User::try_from).collect()
+}
+
+/// Cập nhật hồ sơ user. `None` = giữ nguyên, chuỗi rỗng = xóa giá trị (trừ name).
+pub async fn update_user_profile(
+    pool: &PgPool,
+    id: i32,
+    name: Option<&str>,
+    display_name: Option<&str>,
+    bio: Option<&str>,
+    email: Option<&str>,
+) -> Result<Option<User>, ApiError> {
+    let res = sqlx::query_as!(
+        UserRow,
+        r#"UPDATE users SET
+               name = COALESCE($2, name),
+               display_name = CASE WHEN $3::text IS NULL THEN display_name ELSE NULLIF($3, '') END,
+               bio = CASE WHEN $4::text IS NULL THEN bio ELSE NULLIF($4, '') END,
+               email = CASE WHEN $5::text IS NULL THEN email ELSE NULLIF($5, '') END,
+               -- Đổi email → phải xác thực lại
+               email_verified_at = CASE
+                   WHEN $5::text IS NOT NULL AND lower(NULLIF($5, '')) IS DISTINCT FROM lower(email) THEN NULL
+                   ELSE email_verified_at
+               END
+           WHERE id = $1 AND deleted_at IS NULL
+           RETURNING id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step"#,
+        id,
+        name,
+        display_name,
+        bio,
+        email
+    )
+    .fetch_optional(pool)
+    .await;
+
+    match res {
+        Ok(rec) => rec.map(User::try_from).transpose(),
+        Err(sqlx::Error::Database(db_err)) if db_err.code() == Some("23505".into()) => {
+            if db_err.constraint() == Some("users_email_key") {
+                Err(ApiError::BadRequest("Email already in use".into()))
+            } else {
+                Err(ApiError::UserExists)
+            }
+        }
+        Err(e) => Err(ApiError::InternalError(format!("Database error: {}", e))),
+    }
+}
+
+/// Lấy user theo email (không phân biệt hoa thường)
+pub async fn get_user_by_email(pool: &PgPool, email: &str) -> Result<Option<User>, ApiError> {
+    let rec = sqlx::query_as!(
+        UserRow,
+        r#"SELECT id, name, password_hash, role, created_at, display_name, bio, email, email_verified_at, avatar_path, totp_secret, totp_enabled_at, totp_last_step
+           FROM users WHERE lower(email) = lower($1) AND deleted_at IS NULL"#,
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch error: {}", e)))?;
+
+    rec.map(User::try_from).transpose()
+}
+
+/// Cập nhật mật khẩu (hash) của user
+pub async fn update_user_password(pool: &PgPool, id: i32, hash: &str) -> Result<u64, ApiError> {
+    let res = sqlx::query!("UPDATE users SET password_hash = $1 WHERE id = $2 AND deleted_at IS NULL", hash, id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB update password error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Tính một lần login trước khi kiểm tra mật khẩu (login đúng sẽ xóa bộ đếm sau đó).
+/// Từ lần thứ `max_failures` trở đi, khóa tài khoản `base_secs * 2^(số lần vượt ngưỡng)` giây
+/// (tối đa `max_secs`). Trả về false khi tài khoản đang bị khóa: kiểm tra khóa và tăng bộ đếm
+/// trong cùng một câu lệnh nên các request song song không vượt được giới hạn.
+pub async fn record_login_attempt(
+    pool: &PgPool,
+    id: i32,
+    max_failures: i32,
+    base_secs: i64,
+    max_secs: i64,
+) -> Result<bool, ApiError> {
+    // Số mũ bị chặn ở 30 để power() không tràn (LEAST đã giới hạn ở max_secs từ lâu trước đó)
+    let row = sqlx::query!(
+        r#"UPDATE users SET
+               failed_login_count = failed_login_count + 1,
+               locked_until = CASE
+                   WHEN failed_login_count + 1 >= $2
+                   THEN now() + make_interval(secs => LEAST($3 * power(2, LEAST(failed_login_count + 1 - $2, 30)), $4))
+                   ELSE locked_until
+               END
+           WHERE id = $1 AND (locked_until IS NULL OR locked_until <= now())
+           RETURNING failed_login_count"#,
+        id,
+        max_failures,
+        base_secs as f64,
+        max_secs as f64
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB record login attempt error: {}", e)))?;
+    Ok(row.is_some())
+}
+
+/// Xóa bộ đếm login sai và mở khóa tài khoản
+pub async fn reset_login_failures(pool: &PgPool, id: i32) -> Result<u64, ApiError> {
+    let res = sqlx::query!(
+        "UPDATE users SET failed_login_count = 0, locked_until = NULL WHERE id = $1 AND deleted_at IS NULL",
+        id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB reset login failures error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Cập nhật role của user
+pub async fn update_user_role(pool: &PgPool, id: i32, role: Role) -> Result<u64, ApiError> {
+    let res = sqlx::query!("UPDATE users SET role = $1 WHERE id = $2 AND deleted_at IS NULL", role.as_str(), id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB update role error: {}", e)))?;
+    Ok(res.rows_affected())
 }
 
-/// Xóa user theo ID
+/// Cập nhật role theo tên user (lệnh quản trị `set-role`)
+pub async fn update_user_role_by_name(pool: &PgPool, name: &str, role: Role) -> Result<u64, ApiError> {
+    let res = sqlx::query!("UPDATE users SET role = $1 WHERE name = $2 AND deleted_at IS NULL", role.as_str(), name)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB update role error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Xóa mềm user (đặt deleted_at)
 pub async fn delete_user(pool: &PgPool, id: i32) -> Result<u64, ApiError> {
-    let res = sqlx::query!("DELETE FROM users WHERE id = $1", id)
+    let res = sqlx::query!("UPDATE users SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL", id)
         .execute(pool)
         .await
         .map_err(|e| ApiError::InternalError(format!("DB delete error: {}", e)))?;
     Ok(res.rows_affected())
 }
 
+/// Khôi phục user đã xóa mềm, nếu chưa quá `grace_secs`; lỗi Conflict nếu tên hoặc email
+/// đã có user khác dùng kể từ lúc xóa
+pub async fn restore_user(pool: &PgPool, id: i32, grace_secs: i64) -> Result<u64, ApiError> {
+    let res = sqlx::query!(
+        r#"UPDATE users SET deleted_at = NULL
+           WHERE id = $1 AND deleted_at IS NOT NULL AND deleted_at > now() - make_interval(secs => $2)"#,
+        id,
+        grace_secs as f64
+    )
+    .execute(pool)
+    .await;
+
+    match res {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(sqlx::Error::Database(db_err)) if db_err.code() == Some("23505".into()) => {
+            if db_err.constraint() == Some("users_email_key") {
+                Err(ApiError::Conflict("Email has been taken since the account was deleted".into()))
+            } else {
+                Err(ApiError::Conflict("Name has been taken since the account was deleted".into()))
+            }
+        }
+        Err(e) => Err(ApiError::InternalError(format!("DB restore error: {}", e))),
+    }
+}
+
+/// Xóa hẳn các user đã xóa mềm quá `grace_secs`, trả về avatar_path để dọn file
+pub async fn purge_deleted_users(pool: &PgPool, grace_secs: i64) -> Result<Vec<Option<String>>, ApiError> {
+    let rows = sqlx::query!(
+        r#"DELETE FROM users
+           WHERE deleted_at IS NOT NULL AND deleted_at <= now() - make_interval(secs => $1)
+           RETURNING avatar_path"#,
+        grace_secs as f64
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB purge users error: {}", e)))?;
+    Ok(rows.into_iter().map(|r| r.avatar_path).collect())
+}
+
 /// Cập nhật avatar_path của user
 pub async fn update_user_avatar(pool: &PgPool, id: i32, path: &str) -> Result<u64, ApiError> {
     let res = sqlx::query!(
-        "UPDATE users SET avatar_path = $1 WHERE id = $2",
+        "UPDATE users SET avatar_path = $1 WHERE id = $2 AND deleted_at IS NULL",
         path,
         id
     )
@@ -90,9 +435,730 @@ pub async fn update_user_avatar(pool: &PgPool, id: i32, path: &str) -> Result<u6
 
 /// Lấy avatar_path của user
 pub async fn get_avatar_path(pool: &PgPool, id: i32) -> Result<Option<String>, ApiError> {
-    let rec = sqlx::query!("SELECT avatar_path FROM users WHERE id = $1", id)
+    let rec = sqlx::query!("SELECT avatar_path FROM users WHERE id = $1 AND deleted_at IS NULL", id)
         .fetch_optional(pool)
         .await
         .map_err(|e| ApiError::InternalError(format!("DB fetch avatar error: {}", e)))?;
     Ok(rec.and_then(|r| r.avatar_path))
 }
+
+/// Lưu refresh token (đã hash) mới
+pub async fn create_refresh_token(
+    pool: &PgPool,
+    user_id: i32,
+    family_id: &str,
+    token_hash: &str,
+    scope: Option<&str>,
+    expires_at: DateTime<Utc>,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        r#"INSERT INTO refresh_tokens (user_id, family_id, token_hash, scope, expires_at) VALUES ($1, $2, $3, $4, $5)"#,
+        user_id,
+        family_id,
+        token_hash,
+        scope,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB insert refresh token error: {}", e)))?;
+    Ok(())
+}
+
+/// Xoay vòng refresh token: đánh dấu token cũ đã dùng và lưu token mới cùng family.
+/// Nếu token cũ đã được dùng trước đó → thu hồi toàn bộ family.
+/// Trả về (user_id, family_id, scope) của token family.
+pub async fn rotate_refresh_token(
+    pool: &PgPool,
+    token_hash: &str,
+    new_token_hash: &str,
+    new_expires_at: DateTime<Utc>,
+) -> Result<(i32, String, Option<String>), ApiError> {
+    let db_err = |e: sqlx::Error| ApiError::InternalError(format!("DB refresh token error: {}", e));
+
+    let mut tx = pool.begin().await.map_err(db_err)?;
+
+    let rec = sqlx::query!(
+        r#"SELECT id, user_id, family_id, scope, expires_at, used_at, revoked_at
+           FROM refresh_tokens
+           WHERE token_hash = $1
+           FOR UPDATE"#,
+        token_hash
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_err)?
+    .ok_or_else(|| ApiError::Unauthorized("Invalid refresh token".into()))?;
+
+    // Token đã dùng hoặc đã bị thu hồi xuất hiện lại → thu hồi cả family
+    if rec.used_at.is_some() || rec.revoked_at.is_some() {
+        sqlx::query!(
+            "UPDATE refresh_tokens SET revoked_at = now() WHERE family_id = $1 AND revoked_at IS NULL",
+            rec.family_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+        tx.commit().await.map_err(db_err)?;
+
+        return Err(ApiError::Unauthorized(if rec.used_at.is_some() {
+            "Refresh token reuse detected, please login again".into()
+        } else {
+            "Refresh token revoked".into()
+        }));
+    }
+
+    if rec.expires_at <= Utc::now() {
+        return Err(ApiError::Unauthorized("Refresh token expired".into()));
+    }
+
+    sqlx::query!("UPDATE refresh_tokens SET used_at = now() WHERE id = $1", rec.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+
+    sqlx::query!(
+        r#"INSERT INTO refresh_tokens (user_id, family_id, token_hash, scope, expires_at) VALUES ($1, $2, $3, $4, $5)"#,
+        rec.user_id,
+        rec.family_id,
+        new_token_hash,
+        rec.scope,
+        new_expires_at
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_err)?;
+
+    tx.commit().await.map_err(db_err)?;
+
+    Ok((rec.user_id, rec.family_id, rec.scope))
+}
+
+/// Thu hồi toàn bộ refresh token thuộc một family (session)
+pub async fn revoke_refresh_family(pool: &PgPool, family_id: &str) -> Result<u64, ApiError> {
+    let res = sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = now() WHERE family_id = $1 AND revoked_at IS NULL",
+        family_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB revoke refresh token error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Lưu jti của access token đã bị thu hồi (dọn luôn các bản ghi đã hết hạn)
+pub async fn insert_revoked_token(
+    pool: &PgPool,
+    jti: &str,
+    user_id: i32,
+    expires_at: DateTime<Utc>,
+) -> Result<(), ApiError> {
+    let db_err = |e: sqlx::Error| ApiError::InternalError(format!("DB revoke token error: {}", e));
+
+    sqlx::query!(
+        r#"INSERT INTO revoked_tokens (jti, user_id, expires_at) VALUES ($1, $2, $3) ON CONFLICT (jti) DO NOTHING"#,
+        jti,
+        user_id,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map_err(db_err)?;
+
+    sqlx::query!("DELETE FROM revoked_tokens WHERE expires_at < now()")
+        .execute(pool)
+        .await
+        .map_err(db_err)?;
+    Ok(())
+}
+
+/// Thu hồi mọi token của user: đặt mốc tokens_revoked_before và thu hồi toàn bộ refresh token
+pub async fn revoke_all_user_tokens(pool: &PgPool, user_id: i32) -> Result<DateTime<Utc>, ApiError> {
+    let db_err = |e: sqlx::Error| ApiError::InternalError(format!("DB revoke all tokens error: {}", e));
+
+    let mut tx = pool.begin().await.map_err(db_err)?;
+
+    let rec = sqlx::query!(
+        r#"UPDATE users SET tokens_revoked_before = now() WHERE id = $1 RETURNING tokens_revoked_before AS "revoked_before!""#,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_err)?
+    .ok_or(ApiError::NotFound)?;
+
+    sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_err)?;
+
+    tx.commit().await.map_err(db_err)?;
+    Ok(rec.revoked_before)
+}
+
+/// Kiểm tra trạng thái thu hồi của token: (jti đã bị thu hồi?, mốc tokens_revoked_before của user)
+pub async fn get_token_revocation(
+    pool: &PgPool,
+    jti: &str,
+    user_id: i32,
+) -> Result<(bool, Option<DateTime<Utc>>), ApiError> {
+    let rec = sqlx::query!(
+        r#"SELECT
+               EXISTS(SELECT 1 FROM revoked_tokens WHERE jti = $1) AS "revoked!",
+               (SELECT tokens_revoked_before FROM users WHERE id = $2) AS revoked_before"#,
+        jti,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch revocation error: {}", e)))?;
+    Ok((rec.revoked, rec.revoked_before))
+}
+
+/// Tạo session mới
+pub async fn create_session(
+    pool: &PgPool,
+    id: &str,
+    user_id: i32,
+    ip: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        "INSERT INTO sessions (id, user_id, ip, user_agent) VALUES ($1, $2, $3, $4)",
+        id,
+        user_id,
+        ip,
+        user_agent
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB insert session error: {}", e)))?;
+    Ok(())
+}
+
+/// Cập nhật last_activity nếu session còn hoạt động trong `timeout_secs`.
+/// Trả về None nếu session không tồn tại, Some(false) nếu đã quá thời gian chờ.
+pub async fn touch_session(pool: &PgPool, id: &str, timeout_secs: i64) -> Result<Option<bool>, ApiError> {
+    let db_err = |e: sqlx::Error| ApiError::InternalError(format!("DB touch session error: {}", e));
+
+    let updated = sqlx::query!(
+        r#"UPDATE sessions SET last_activity = now()
+           WHERE id = $1 AND last_activity > now() - make_interval(secs => $2)
+           RETURNING id"#,
+        id,
+        timeout_secs as f64
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(db_err)?;
+
+    if updated.is_some() {
+        return Ok(Some(true));
+    }
+
+    let exists = sqlx::query!(r#"SELECT EXISTS(SELECT 1 FROM sessions WHERE id = $1) AS "exists!""#, id)
+        .fetch_one(pool)
+        .await
+        .map_err(db_err)?;
+    Ok(exists.exists.then_some(false))
+}
+
+/// Lấy session theo ID
+pub async fn get_session(pool: &PgPool, id: &str) -> Result<Option<Session>, ApiError> {
+    sqlx::query_as!(
+        Session,
+        "SELECT id, user_id, created_at, last_activity, ip, user_agent FROM sessions WHERE id = $1",
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch session error: {}", e)))
+}
+
+/// Danh sách session của user (mới hoạt động gần nhất trước)
+pub async fn list_user_sessions(pool: &PgPool, user_id: i32) -> Result<Vec<Session>, ApiError> {
+    sqlx::query_as!(
+        Session,
+        r#"SELECT id, user_id, created_at, last_activity, ip, user_agent
+           FROM sessions WHERE user_id = $1
+           ORDER BY last_activity DESC"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch sessions error: {}", e)))
+}
+
+/// Kích hoạt lại session (đặt last_activity = now), trả về false nếu session không tồn tại
+pub async fn resume_session(pool: &PgPool, id: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query!("UPDATE sessions SET last_activity = now() WHERE id = $1", id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB resume session error: {}", e)))?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Xóa một session
+pub async fn delete_session(pool: &PgPool, id: &str) -> Result<u64, ApiError> {
+    let res = sqlx::query!("DELETE FROM sessions WHERE id = $1", id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB delete session error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Xóa mọi session của user
+pub async fn delete_user_sessions(pool: &PgPool, user_id: i32) -> Result<u64, ApiError> {
+    let res = sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB delete sessions error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Xóa các session không hoạt động lâu hơn `max_idle_secs`
+pub async fn purge_stale_sessions(pool: &PgPool, max_idle_secs: i64) -> Result<u64, ApiError> {
+    let res = sqlx::query!(
+        "DELETE FROM sessions WHERE last_activity < now() - make_interval(secs => $1)",
+        max_idle_secs as f64
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB purge sessions error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Tạo API key (chỉ lưu prefix và hash Argon2)
+pub async fn create_api_key(
+    pool: &PgPool,
+    user_id: i32,
+    name: &str,
+    prefix: &str,
+    key_hash: &str,
+    scope: &str,
+) -> Result<ApiKey, ApiError> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"INSERT INTO api_keys (user_id, name, prefix, key_hash, scope) VALUES ($1, $2, $3, $4, $5)
+           RETURNING id, user_id, name, prefix, key_hash, scope, created_at, last_used_at, revoked_at"#,
+        user_id,
+        name,
+        prefix,
+        key_hash,
+        scope
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB insert api key error: {}", e)))
+}
+
+/// Lấy API key theo prefix
+pub async fn get_api_key_by_prefix(pool: &PgPool, prefix: &str) -> Result<Option<ApiKey>, ApiError> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"SELECT id, user_id, name, prefix, key_hash, scope, created_at, last_used_at, revoked_at
+           FROM api_keys WHERE prefix = $1"#,
+        prefix
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch api key error: {}", e)))
+}
+
+/// Danh sách API key của user
+pub async fn list_api_keys(pool: &PgPool, user_id: i32) -> Result<Vec<ApiKey>, ApiError> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"SELECT id, user_id, name, prefix, key_hash, scope, created_at, last_used_at, revoked_at
+           FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch api keys error: {}", e)))
+}
+
+/// Thu hồi API key của user, trả về false nếu không tìm thấy
+pub async fn revoke_api_key(pool: &PgPool, user_id: i32, key_id: i32) -> Result<bool, ApiError> {
+    let res = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        key_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB revoke api key error: {}", e)))?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Thu hồi mọi API key còn hiệu lực của user (đổi / đặt lại mật khẩu)
+pub async fn revoke_user_api_keys(pool: &PgPool, user_id: i32) -> Result<u64, ApiError> {
+    let res = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB revoke api keys error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// API key còn hiệu lực (chưa bị thu hồi) không
+pub async fn is_api_key_active(pool: &PgPool, key_id: i32) -> Result<bool, ApiError> {
+    let rec = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM api_keys WHERE id = $1 AND revoked_at IS NULL) AS "active!""#,
+        key_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch api key error: {}", e)))?;
+    Ok(rec.active)
+}
+
+/// Ghi nhận thời điểm dùng API key
+pub async fn touch_api_key(pool: &PgPool, key_id: i32) -> Result<(), ApiError> {
+    sqlx::query!("UPDATE api_keys SET last_used_at = now() WHERE id = $1", key_id)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB touch api key error: {}", e)))?;
+    Ok(())
+}
+
+/// Lưu token đặt lại mật khẩu (đã hash)
+pub async fn create_password_reset_token(
+    pool: &PgPool,
+    user_id: i32,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        user_id,
+        token_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB insert reset token error: {}", e)))?;
+    Ok(())
+}
+
+/// user_id của token đặt lại mật khẩu còn hiệu lực (không đánh dấu đã dùng)
+pub async fn get_password_reset_token_user(pool: &PgPool, token_hash: &str) -> Result<Option<i32>, ApiError> {
+    let rec = sqlx::query!(
+        "SELECT user_id FROM password_reset_tokens WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()",
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch reset token error: {}", e)))?;
+    Ok(rec.map(|r| r.user_id))
+}
+
+/// Dùng token đặt lại mật khẩu (chỉ một lần, còn hạn), trả về user_id.
+/// Các token khác của user cũng bị vô hiệu hóa.
+pub async fn consume_password_reset_token(pool: &PgPool, token_hash: &str) -> Result<Option<i32>, ApiError> {
+    let rec = sqlx::query!(
+        r#"WITH consumed AS (
+               UPDATE password_reset_tokens SET used_at = now()
+               WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+               RETURNING user_id
+           ), others AS (
+               UPDATE password_reset_tokens SET used_at = now()
+               WHERE user_id IN (SELECT user_id FROM consumed) AND token_hash <> $1 AND used_at IS NULL
+           )
+           SELECT user_id FROM consumed"#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB consume reset token error: {}", e)))?;
+    Ok(rec.map(|r| r.user_id))
+}
+
+/// Xóa token đặt lại mật khẩu đã dùng hoặc hết hạn
+pub async fn purge_password_reset_tokens(pool: &PgPool) -> Result<u64, ApiError> {
+    let res = sqlx::query!("DELETE FROM password_reset_tokens WHERE used_at IS NOT NULL OR expires_at <= now()")
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB purge reset tokens error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Lưu mã xác thực email (đã hash)
+pub async fn create_email_verification_token(
+    pool: &PgPool,
+    user_id: i32,
+    email: &str,
+    code_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        "INSERT INTO email_verification_tokens (user_id, email, code_hash, expires_at) VALUES ($1, $2, $3, $4)",
+        user_id,
+        email,
+        code_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB insert verification token error: {}", e)))?;
+    Ok(())
+}
+
+/// Dùng mã xác thực email (một lần, còn hạn) và đánh dấu email đã xác thực.
+/// Trả về false nếu mã sai / hết hạn hoặc user đã đổi sang email khác.
+pub async fn verify_email_token(pool: &PgPool, code_hash: &str) -> Result<bool, ApiError> {
+    let rec = sqlx::query!(
+        r#"WITH consumed AS (
+               UPDATE email_verification_tokens SET used_at = now()
+               WHERE code_hash = $1 AND used_at IS NULL AND expires_at > now()
+               RETURNING user_id, email
+           )
+           UPDATE users SET email_verified_at = now()
+           FROM consumed
+           WHERE users.id = consumed.user_id AND lower(users.email) = lower(consumed.email)
+           RETURNING users.id"#,
+        code_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB verify email error: {}", e)))?;
+    Ok(rec.is_some())
+}
+
+/// Xóa mã xác thực email đã dùng hoặc hết hạn
+pub async fn purge_email_verification_tokens(pool: &PgPool) -> Result<u64, ApiError> {
+    let res = sqlx::query!("DELETE FROM email_verification_tokens WHERE used_at IS NOT NULL OR expires_at <= now()")
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB purge verification tokens error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Lưu secret TOTP đang chờ xác nhận (chỉ khi 2FA chưa bật)
+pub async fn set_totp_secret(pool: &PgPool, id: i32, secret: &str) -> Result<u64, ApiError> {
+    let res = sqlx::query!(
+        "UPDATE users SET totp_secret = $2, totp_last_step = NULL WHERE id = $1 AND totp_enabled_at IS NULL",
+        id,
+        secret
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB set totp secret error: {}", e)))?;
+    Ok(res.rows_affected())
+}
+
+/// Bật 2FA và thay toàn bộ mã khôi phục (đã hash), trả về false nếu 2FA đã bật
+pub async fn enable_totp(pool: &PgPool, id: i32, step: i64, code_hashes: &[String]) -> Result<bool, ApiError> {
+    let map_err = |e: sqlx::Error| ApiError::InternalError(format!("DB enable totp error: {}", e));
+    let mut tx = pool.begin().await.map_err(map_err)?;
+
+    let res = sqlx::query!(
+        r#"UPDATE users SET totp_enabled_at = now(), totp_last_step = $2
+           WHERE id = $1 AND totp_enabled_at IS NULL AND totp_secret IS NOT NULL"#,
+        id,
+        step
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(map_err)?;
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", id)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?;
+    sqlx::query!(
+        "INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::text[])",
+        id,
+        code_hashes
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(map_err)?;
+
+    tx.commit().await.map_err(map_err)?;
+    Ok(true)
+}
+
+/// Ghi nhận time step TOTP vừa dùng; false nếu mã của step này (hoặc mới hơn) đã được dùng
+pub async fn consume_totp_step(pool: &PgPool, id: i32, step: i64) -> Result<bool, ApiError> {
+    let res = sqlx::query!(
+        "UPDATE users SET totp_last_step = $2 WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)",
+        id,
+        step
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB consume totp step error: {}", e)))?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Dùng một mã khôi phục (một lần)
+pub async fn use_recovery_code(pool: &PgPool, user_id: i32, code_hash: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query!(
+        r#"UPDATE recovery_codes SET used_at = now()
+           WHERE id = (
+               SELECT id FROM recovery_codes
+               WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+               LIMIT 1
+           )"#,
+        user_id,
+        code_hash
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB use recovery code error: {}", e)))?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Tạo challenge "mfa_required" (dọn các challenge đã hết hạn)
+pub async fn create_mfa_challenge(
+    pool: &PgPool,
+    token_hash: &str,
+    user_id: i32,
+    expires_at: DateTime<Utc>,
+) -> Result<(), ApiError> {
+    sqlx::query!("DELETE FROM mfa_challenges WHERE expires_at <= now()")
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB purge mfa challenges error: {}", e)))?;
+
+    sqlx::query!(
+        "INSERT INTO mfa_challenges (token_hash, user_id, expires_at) VALUES ($1, $2, $3)",
+        token_hash,
+        user_id,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB insert mfa challenge error: {}", e)))?;
+    Ok(())
+}
+
+/// Tính một lần nhập mã cho challenge còn hạn (trước khi kiểm tra mã) và trả về user_id;
+/// None nếu challenge không tồn tại, đã hết hạn hoặc đã dùng hết số lần nhập
+pub async fn claim_mfa_attempt(pool: &PgPool, token_hash: &str, max_attempts: i32) -> Result<Option<i32>, ApiError> {
+    let rec = sqlx::query!(
+        r#"UPDATE mfa_challenges SET attempts = attempts + 1
+           WHERE token_hash = $1 AND expires_at > now() AND attempts < $2
+           RETURNING user_id"#,
+        token_hash,
+        max_attempts
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB update mfa challenge error: {}", e)))?;
+    Ok(rec.map(|r| r.user_id))
+}
+
+/// Xóa challenge sau khi dùng; false nếu challenge đã được dùng trước đó
+pub async fn delete_mfa_challenge(pool: &PgPool, token_hash: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query!("DELETE FROM mfa_challenges WHERE token_hash = $1", token_hash)
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB delete mfa challenge error: {}", e)))?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Ghi một sự kiện audit
+pub async fn insert_audit_event(
+    pool: &PgPool,
+    actor_id: Option<i32>,
+    target_user_id: Option<i32>,
+    action: &str,
+    ip: Option<&str>,
+    user_agent: Option<&str>,
+    metadata: &serde_json::Value,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        r#"INSERT INTO audit_events (actor_id, target_user_id, action, ip, user_agent, metadata)
+           VALUES ($1, $2, $3, $4, $5, $6)"#,
+        actor_id,
+        target_user_id,
+        action,
+        ip,
+        user_agent,
+        metadata
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB insert audit event error: {}", e)))?;
+    Ok(())
+}
+
+/// Danh sách sự kiện audit, mới nhất trước (keyset pagination theo id)
+pub async fn list_audit_events(
+    pool: &PgPool,
+    user_id: Option<i32>,
+    action: Option<&str>,
+    since: Option<DateTime<Utc>>,
+    before_id: Option<i64>,
+    limit: i64,
+) -> Result<Vec<AuditEvent>, ApiError> {
+    sqlx::query_as!(
+        AuditEvent,
+        r#"SELECT id, actor_id, target_user_id, action, ip, user_agent, metadata, created_at
+           FROM audit_events
+           WHERE ($1::int IS NULL OR actor_id = $1 OR target_user_id = $1)
+             AND ($2::text IS NULL OR action = $2)
+             AND ($3::timestamptz IS NULL OR created_at >= $3)
+             AND ($4::bigint IS NULL OR id < $4)
+           ORDER BY id DESC
+           LIMIT $5"#,
+        user_id,
+        action,
+        since,
+        before_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB fetch audit events error: {}", e)))
+}
+
+/// Kết quả của hàm SQL `rate_limit_acquire`
+pub struct RateLimitAcquire {
+    pub allowed: bool,
+    /// State sau khi tính (giữ nguyên nếu bị từ chối)
+    pub state: Vec<f64>,
+    /// Thời điểm của DB (giây Unix) đã dùng để tính
+    pub now: f64,
+}
+
+/// Tính một request cho key trong một câu lệnh (xem migrations/018_create_rate_limit_acquire.sql)
+pub async fn acquire_rate_limit(
+    pool: &PgPool,
+    key: &str,
+    algorithm: &str,
+    max_requests: i32,
+    window_secs: f64,
+) -> Result<RateLimitAcquire, ApiError> {
+    let row = sqlx::query!(
+        r#"SELECT allowed AS "allowed!", state AS "state!", now_secs AS "now_secs!"
+           FROM rate_limit_acquire($1, $2, $3, $4)"#,
+        key,
+        algorithm,
+        max_requests,
+        window_secs
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::InternalError(format!("DB rate limit error: {}", e)))?;
+    Ok(RateLimitAcquire { allowed: row.allowed, state: row.state, now: row.now_secs })
+}
+
+/// Xóa state rate limit đã hết hạn
+pub async fn purge_rate_limit_state(pool: &PgPool) -> Result<u64, ApiError> {
+    let res = sqlx::query!("DELETE FROM rate_limit_state WHERE expires_at <= now()")
+        .execute(pool)
+        .await
+        .map_err(|e| ApiError::InternalError(format!("DB purge rate limit error: {}", e)))?;
+    Ok(res.rows_affected())
+}
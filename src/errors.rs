@@ -6,6 +6,10 @@ pub enum ApiError {
     #[error("Bad Request: {0}")]
     BadRequest(String),
 
+    /// Bad Request kèm danh sách chi tiết (vd. mọi quy tắc mật khẩu bị vi phạm)
+    #[error("Bad Request: {0}")]
+    BadRequestDetails(String, Vec<String>),
+
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
@@ -18,19 +22,41 @@ pub enum ApiError {
     #[error("User already exists")]
     UserExists,
 
-    #[error("You can only delete your own account")]
+    #[error("You can only access your own account")]
     NotAllowed,
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// Vượt rate limit; `retry_after_secs` được trả qua header Retry-After
+    #[error("Too many requests. Only {limit} requests per {window_secs} seconds allowed.")]
+    TooManyRequests { limit: usize, window_secs: u64, retry_after_secs: u64 },
 }
 
 impl ApiError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::BadRequestDetails(..) => StatusCode::BAD_REQUEST,
             ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
             ApiError::NotFound => StatusCode::NOT_FOUND,
             ApiError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::UserExists => StatusCode::BAD_REQUEST,
             ApiError::NotAllowed => StatusCode::FORBIDDEN,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// Chi tiết lỗi (nếu có) để trả về trong trường `details` của response
+    pub fn details(&self) -> Option<&[String]> {
+        match self {
+            ApiError::BadRequestDetails(_, details) => Some(details),
+            _ => None,
         }
     }
 }
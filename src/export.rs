@@ -0,0 +1,138 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::mpsc;
+use warp::hyper::body::{Body, Bytes};
+
+/// Số sự kiện audit tối đa trong một gói xuất và số sự kiện đọc mỗi lần từ DB
+pub const MAX_AUDIT_EVENTS: i64 = 10_000;
+pub const AUDIT_PAGE_SIZE: i64 = 500;
+
+/// Kích thước mỗi chunk gửi xuống response
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Writer (đồng bộ) gửi từng chunk vào body của response, dùng với `zip::ZipWriter::new_stream`
+/// (chạy trong spawn_blocking); lỗi khi client đã ngắt kết nối
+pub struct BodyWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+    aborted: Arc<AtomicBool>,
+}
+
+/// Cắt response giữa chừng khi gói xuất bị lỗi
+pub struct BodyAbort {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    aborted: Arc<AtomicBool>,
+}
+
+impl BodyAbort {
+    /// Client thấy kết nối bị cắt thay vì nhận một file ZIP thiếu mà tưởng là đủ. Dữ liệu ghi sau đó
+    /// (vd. central directory do ZipWriter ghi khi bị drop) bị bỏ qua.
+    pub fn abort(&self, err: io::Error) {
+        if !self.aborted.swap(true, Ordering::SeqCst) {
+            let _ = self.tx.blocking_send(Err(err));
+        }
+    }
+}
+
+impl BodyWriter {
+    pub fn abort_handle(&self) -> BodyAbort {
+        BodyAbort { tx: self.tx.clone(), aborted: self.aborted.clone() }
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.aborted.load(Ordering::SeqCst) {
+            self.buf.clear();
+            return Ok(());
+        }
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buf));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))
+    }
+}
+
+impl Drop for BodyWriter {
+    /// Gửi nốt phần còn trong bộ đệm (ZipWriter::finish trả writer về mà không flush)
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::debug!("Export body not flushed: {}", e);
+        }
+    }
+}
+
+/// Tạo cặp (writer, body): dữ liệu ghi vào writer được stream xuống client
+pub fn body_channel() -> (BodyWriter, Body) {
+    let (tx, rx) = mpsc::channel(4);
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    let writer = BodyWriter {
+        tx,
+        buf: Vec::with_capacity(CHUNK_SIZE),
+        aborted: Arc::new(AtomicBool::new(false)),
+    };
+    (writer, Body::wrap_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[tokio::test]
+    async fn streamed_archive_is_readable() {
+        let (writer, body) = body_channel();
+        let large: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let expected = large.clone();
+        let task = tokio::task::spawn_blocking(move || -> zip::result::ZipResult<()> {
+            let mut zip = ZipWriter::new_stream(writer);
+            zip.start_file("export.json", SimpleFileOptions::default().large_file(true))?;
+            zip.write_all(b"{\"a\":1}")?;
+            zip.start_file("avatar/a.png", SimpleFileOptions::default())?;
+            zip.write_all(&large)?;
+            zip.finish()?;
+            Ok(())
+        });
+
+        let bytes = warp::hyper::body::to_bytes(body).await.unwrap();
+        task.await.unwrap().unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut json = String::new();
+        archive.by_name("export.json").unwrap().read_to_string(&mut json).unwrap();
+        assert_eq!(json, "{\"a\":1}");
+        let mut avatar = Vec::new();
+        archive.by_name("avatar/a.png").unwrap().read_to_end(&mut avatar).unwrap();
+        assert_eq!(avatar, expected);
+    }
+
+    #[tokio::test]
+    async fn aborted_archive_fails_the_body() {
+        let (writer, body) = body_channel();
+        tokio::task::spawn_blocking(move || {
+            let abort = writer.abort_handle();
+            let mut zip = ZipWriter::new_stream(writer);
+            zip.start_file("export.json", SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"partial").unwrap();
+            abort.abort(io::Error::other("db error"));
+            // ZipWriter bị drop sẽ tự ghi central directory; phần này phải bị bỏ qua
+        });
+
+        assert!(warp::hyper::body::to_bytes(body).await.is_err());
+    }
+}
@@ -1,15 +1,30 @@
-use crate::models::{RegisterRequest, LoginRequest, UserResponse, AvatarResponse};
+use crate::models::{RegisterRequest, LoginRequest, ChangePasswordRequest, ForgotPasswordRequest, ResetPasswordRequest, VerifyEmailRequest, TotpCodeRequest, MfaLoginRequest, RefreshRequest, RoleRequest, ScopedTokenRequest, CreateApiKeyRequest, User, UserResponse, UserListQuery, UserListResponse, UpdateUserRequest, ProfileResponse, UserSort, UserCursor, AuditQuery, AuditListResponse, AvatarResponse, SessionResponse, ApiKeyResponse};
+use crate::api_keys;
+use crate::audit;
 use crate::errors::ApiError;
 use crate::db;
 use crate::jwt;
+use crate::keys;
+use crate::mailer::{self, Email};
+use crate::config::CONFIG;
+use crate::revocation;
+use crate::scopes;
+use crate::session;
+use crate::totp;
+use crate::export::{self, BodyWriter};
+use crate::password_policy;
 use sqlx::PgPool;
 use warp::http::StatusCode;
 use futures_util::StreamExt;
 use futures_util::TryStreamExt;
+use std::net::IpAddr;
 use std::path::Path;
 use tokio::io::AsyncWriteExt;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use warp::Buf;
+use zip::result::ZipResult;
+use zip::write::{SimpleFileOptions, StreamWriter};
+use zip::ZipWriter;
 
 /// Root handler
 pub async fn root_handler() -> Result<impl warp::Reply, warp::Rejection> {
@@ -18,19 +33,39 @@ pub async fn root_handler() -> Result<impl warp::Reply, warp::Rejection> {
     })))
 }
 
+/// JWKS handler: public key để service khác xác thực token
+pub async fn jwks_handler() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::with_header(
+        warp::reply::json(&keys::jwks()),
+        "cache-control",
+        "public, max-age=300",
+    ))
+}
+
 /// Register handler
-pub async fn register_handler(body: RegisterRequest, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+pub async fn register_handler(
+    body: RegisterRequest,
+    pool: PgPool,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
     if body.name.trim().is_empty() {
         return Err(warp::reject::custom(ApiError::BadRequest("Name cannot be empty".into())));
     }
-    if body.password.len() < 8 {
-        return Err(warp::reject::custom(ApiError::BadRequest("Password must be at least 8 chars".into())));
+    check_new_password(&body.password, &body.name)?;
+    let email = body.email.as_deref().map(str::trim).filter(|e| !e.is_empty());
+    match email {
+        Some(e) => check_email(e)?,
+        None if CONFIG.require_verified_email => {
+            return Err(warp::reject::custom(ApiError::BadRequest("Email is required".into())));
+        }
+        None => {}
     }
 
     let hash = db::hash_password(&body.password)
         .map_err(|_| warp::reject::custom(ApiError::InternalError("Password hash failed".into())))?;
 
-    let id = db::create_user(&pool, &body.name, &hash)
+    db::create_user(&pool, &body.name, &hash, email)
         .await
         .map_err(warp::reject::custom)?;
 
@@ -38,46 +73,915 @@ pub async fn register_handler(body: RegisterRequest, pool: PgPool) -> Result<imp
         .await
         .map_err(warp::reject::custom)?;
 
-    let (_id, _name, _hash, created_at) =
+    let user =
         user_opt.ok_or_else(|| warp::reject::custom(ApiError::InternalError("User retrieval failed".into())))?;
 
-    let resp = UserResponse { id, name: body.name, created_at };
+    if user.email.is_some() {
+        send_email_verification(&pool, &user).await?;
+    }
+    audit::record(
+        &pool,
+        audit::USER_REGISTER,
+        Some(user.id),
+        Some(user.id),
+        &audit::Client::new(client_ip, user_agent),
+        serde_json::json!({ "name": user.name }),
+    );
+
+    let resp = UserResponse::from(user);
     Ok(warp::reply::with_status(warp::reply::json(&resp), StatusCode::CREATED))
 }
 
+/// Kiểm tra mật khẩu mới theo PasswordPolicy (register / đổi / đặt lại mật khẩu)
+fn check_new_password(password: &str, username: &str) -> Result<(), warp::Rejection> {
+    password_policy::check(password, username).map_err(warp::reject::custom)
+}
+
+/// Kiểm tra định dạng email (đơn giản: local@domain.tld)
+fn check_email(email: &str) -> Result<(), warp::Rejection> {
+    if !email.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.')) {
+        return Err(warp::reject::custom(ApiError::BadRequest("Invalid email".into())));
+    }
+    Ok(())
+}
+
+/// Gửi mã xác thực tới email hiện tại của user
+async fn send_email_verification(pool: &PgPool, user: &User) -> Result<(), warp::Rejection> {
+    let Some(email) = user.email.as_deref() else {
+        return Err(warp::reject::custom(ApiError::BadRequest("No email address set".into())));
+    };
+
+    let code = jwt::generate_opaque_token(16);
+    let expires_at = Utc::now() + Duration::hours(CONFIG.email_verification_ttl_hours);
+    db::create_email_verification_token(pool, user.id, email, &jwt::hash_refresh_token(&code), expires_at)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let mail = Email {
+        to: email.to_string(),
+        subject: "Verify your email".into(),
+        body: format!(
+            "Hi {},\n\nYour verification code is: {}\nOr open {}/verify-email?code={} (valid for {} hours).\n",
+            user.name, code, CONFIG.app_base_url, code, CONFIG.email_verification_ttl_hours
+        ),
+    };
+    if let Err(e) = mailer::mailer().send(&mail).await {
+        tracing::warn!("Verification email failed: {}", e);
+    }
+    Ok(())
+}
+
+/// Lưu mật khẩu mới, đăng xuất user khỏi mọi phiên và thu hồi mọi API key
+async fn set_password(pool: &PgPool, user_id: i32, password: &str) -> Result<(), warp::Rejection> {
+    let hash = db::hash_password(password)
+        .map_err(|_| warp::reject::custom(ApiError::InternalError("Password hash failed".into())))?;
+    let rows = db::update_user_password(pool, user_id, &hash)
+        .await
+        .map_err(warp::reject::custom)?;
+    if rows == 0 {
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+
+    revocation::revoke_all_for_user(pool, user_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    session::store().remove_user(user_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    db::revoke_user_api_keys(pool, user_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    api_keys::forget_user(user_id);
+    Ok(())
+}
+
 /// Login handler
-pub async fn login_handler(body: LoginRequest, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+pub async fn login_handler(
+    body: LoginRequest,
+    pool: PgPool,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
     if body.name.trim().is_empty() {
         return Err(warp::reject::custom(ApiError::BadRequest("Name cannot be empty".into())));
     }
 
-    let user_opt = db::get_user_by_name(&pool, &body.name)
+    // User không tồn tại, sai mật khẩu hay tài khoản đang bị khóa đều trả cùng một lỗi,
+    // và luôn chạy Argon2 để thời gian phản hồi như nhau
+    let invalid = || warp::reject::custom(ApiError::Unauthorized("Invalid credentials".into()));
+    let client = audit::Client::new(client_ip, user_agent.clone());
+    let login_failed = async |user_id: Option<i32>, reason: &str| {
+        audit::record_now(
+            &pool,
+            audit::LOGIN_FAILED,
+            user_id,
+            user_id,
+            &client,
+            serde_json::json!({ "name": body.name, "reason": reason }),
+        )
         .await
-        .map_err(warp::reject::custom)?;
+    };
 
-    let (user_id, _name, password_hash, _created_at) = match user_opt {
+    let user = match db::get_user_by_name(&pool, &body.name).await.map_err(warp::reject::custom)? {
         Some(u) => u,
-        None => return Err(warp::reject::custom(ApiError::Unauthorized("User not found".into()))),
+        None => {
+            db::verify_dummy_password(&body.password);
+            login_failed(None, "unknown_user").await;
+            return Err(invalid());
+        }
     };
+    // Tính lần thử trước khi chạy Argon2: N request song song không thể cùng lọt qua kiểm tra khóa
+    let allowed = db::record_login_attempt(
+        &pool,
+        user.id,
+        CONFIG.login_max_failures,
+        CONFIG.login_lockout_base_secs,
+        CONFIG.login_lockout_max_secs,
+    )
+    .await
+    .map_err(warp::reject::custom)?;
+    if !allowed {
+        db::verify_dummy_password(&body.password);
+        login_failed(Some(user.id), "locked").await;
+        return Err(invalid());
+    }
 
-    let verified = db::verify_password(&password_hash, &body.password)
+    let verified = db::verify_password(&user.password_hash, &body.password)
         .map_err(|_| warp::reject::custom(ApiError::InternalError("Password verification failed".into())))?;
 
     if !verified {
-        return Err(warp::reject::custom(ApiError::Unauthorized("Incorrect password".into())));
+        login_failed(Some(user.id), "bad_password").await;
+        return Err(invalid());
     }
+    db::reset_login_failures(&pool, user.id)
+        .await
+        .map_err(warp::reject::custom)?;
 
-    let token = jwt::create_token(user_id, &body.name)
-        .map_err(|_| warp::reject::custom(ApiError::InternalError("JWT creation failed".into())))?;
+    // Hash cũ dùng tham số Argon2 yếu hơn config hiện tại → hash lại bằng mật khẩu vừa nhập
+    if db::needs_rehash(&user.password_hash) {
+        let hash = db::hash_password(&body.password)
+            .map_err(|_| warp::reject::custom(ApiError::InternalError("Password hash failed".into())))?;
+        db::update_user_password(&pool, user.id, &hash)
+            .await
+            .map_err(warp::reject::custom)?;
+    }
+    if CONFIG.require_verified_email && user.email_verified_at.is_none() {
+        return Err(warp::reject::custom(ApiError::Forbidden("Email not verified".into())));
+    }
+
+    // Đã bật 2FA → chỉ trả challenge, JWT được cấp ở /login/mfa
+    if user.totp_enabled_at.is_some() {
+        let challenge = jwt::generate_opaque_token(32);
+        let expires_at = Utc::now() + Duration::seconds(totp::CHALLENGE_TTL_SECS);
+        db::create_mfa_challenge(&pool, &jwt::hash_refresh_token(&challenge), user.id, expires_at)
+            .await
+            .map_err(warp::reject::custom)?;
+        audit::record(&pool, audit::MFA_CHALLENGE, Some(user.id), Some(user.id), &client, serde_json::json!({}));
+
+        return Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({
+            "message": "MFA required",
+            "mfa_required": true,
+            "challenge_token": challenge,
+            "expires_in": totp::CHALLENGE_TTL_SECS
+        })), StatusCode::OK));
+    }
+
+    let (token, refresh_token) = start_session(&pool, &user, None, client_ip, user_agent).await?;
+    audit::record(&pool, audit::LOGIN, Some(user.id), Some(user.id), &client, serde_json::json!({ "mfa": false }));
+
+    Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({
+        "message": "Login successful",
+        "token": token,
+        "token_type": "Bearer",
+        "expires_in": jwt::ACCESS_TOKEN_TTL_MINS * 60,
+        "refresh_token": refresh_token
+    })), StatusCode::OK))
+}
+
+/// MFA login handler: đổi challenge + mã TOTP (hoặc mã khôi phục) lấy JWT
+pub async fn login_mfa_handler(
+    body: MfaLoginRequest,
+    pool: PgPool,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let client = audit::Client::new(client_ip, user_agent.clone());
+    let challenge_hash = jwt::hash_refresh_token(body.challenge_token.trim());
+    // Lần nhập được tính ngay (atomic) trước khi kiểm tra mã, nên request song song không vượt quá giới hạn
+    let user_id = db::claim_mfa_attempt(&pool, &challenge_hash, totp::MAX_CHALLENGE_ATTEMPTS)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::Unauthorized("Invalid or expired MFA challenge".into())))?;
+
+    let user = db::get_user_by_id(&pool, user_id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::Unauthorized("User not found".into())))?;
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .filter(|_| user.totp_enabled_at.is_some())
+        .ok_or_else(|| warp::reject::custom(ApiError::Unauthorized("2FA is not enabled".into())))?;
+
+    let valid = match totp::verify(secret, &body.code, user.totp_last_step) {
+        Some(step) => db::consume_totp_step(&pool, user.id, step)
+            .await
+            .map_err(warp::reject::custom)?,
+        None => {
+            let code_hash = jwt::hash_refresh_token(&totp::normalize_recovery_code(&body.code));
+            db::use_recovery_code(&pool, user.id, &code_hash)
+                .await
+                .map_err(warp::reject::custom)?
+        }
+    };
+    if !valid {
+        audit::record_now(
+            &pool,
+            audit::LOGIN_FAILED,
+            Some(user.id),
+            Some(user.id),
+            &client,
+            serde_json::json!({ "name": user.name, "reason": "bad_mfa_code" }),
+        )
+        .await;
+        return Err(warp::reject::custom(ApiError::Unauthorized("Invalid MFA code".into())));
+    }
+
+    // Challenge chỉ dùng được một lần
+    let consumed = db::delete_mfa_challenge(&pool, &challenge_hash)
+        .await
+        .map_err(warp::reject::custom)?;
+    if !consumed {
+        return Err(warp::reject::custom(ApiError::Unauthorized("Invalid or expired MFA challenge".into())));
+    }
+
+    let (token, refresh_token) = start_session(&pool, &user, None, client_ip, user_agent).await?;
+    audit::record(&pool, audit::LOGIN, Some(user.id), Some(user.id), &client, serde_json::json!({ "mfa": true }));
 
     Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({
         "message": "Login successful",
-        "token": token
+        "token": token,
+        "token_type": "Bearer",
+        "expires_in": jwt::ACCESS_TOKEN_TTL_MINS * 60,
+        "refresh_token": refresh_token
     })), StatusCode::OK))
 }
 
+/// Mở một phiên mới (cũng là token family của refresh token) và cấp cặp access/refresh token.
+/// `scope` = None → toàn bộ scope mặc định theo role.
+async fn start_session(
+    pool: &PgPool,
+    user: &User,
+    scope: Option<&str>,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+) -> Result<(String, String), warp::Rejection> {
+    let sid = jwt::generate_opaque_token(16);
+    let ip = client_ip.map(|ip| ip.to_string());
+    session::store().create(&sid, user.id, ip.as_deref(), user_agent.as_deref())
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let granted = scopes::grant(user.role, scope);
+    let token = jwt::create_token(user.id, &user.name, user.role, &granted, &sid)
+        .map_err(|_| warp::reject::custom(ApiError::InternalError("JWT creation failed".into())))?;
+
+    let refresh_token = jwt::generate_opaque_token(32);
+    let refresh_expires_at = Utc::now() + Duration::days(jwt::REFRESH_TOKEN_TTL_DAYS);
+    db::create_refresh_token(pool, user.id, &sid, &jwt::hash_refresh_token(&refresh_token), scope, refresh_expires_at)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok((token, refresh_token))
+}
+
+/// Scoped token handler: cấp token giới hạn scope (vd. cho automation) từ token hiện tại
+pub async fn scoped_token_handler(
+    body: ScopedTokenRequest,
+    pool: PgPool,
+    claims: jwt::Claims,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if body.scopes.is_empty() {
+        return Err(warp::reject::custom(ApiError::BadRequest("Scopes cannot be empty".into())));
+    }
+    // Chỉ được cấp scope mà token hiện tại đang có
+    if let Some(missing) = body.scopes.iter().find(|s| !claims.has_scope(s)) {
+        return Err(warp::reject::custom(ApiError::Forbidden(format!("Scope {} is not granted to this token", missing))));
+    }
+
+    let user = db::get_user_by_id(&pool, claims.sub)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))?;
+
+    let scope = body.scopes.join(" ");
+    let (token, refresh_token) = start_session(&pool, &user, Some(&scope), client_ip, user_agent).await?;
+
+    Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({
+        "message": "Scoped token issued",
+        "token": token,
+        "token_type": "Bearer",
+        "expires_in": jwt::ACCESS_TOKEN_TTL_MINS * 60,
+        "refresh_token": refresh_token,
+        "scope": scope
+    })), StatusCode::CREATED))
+}
+
+/// Refresh token handler: đổi refresh token lấy access token mới (xoay vòng refresh token)
+pub async fn refresh_token_handler(body: RefreshRequest, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    if body.refresh_token.trim().is_empty() {
+        return Err(warp::reject::custom(ApiError::BadRequest("Refresh token cannot be empty".into())));
+    }
+
+    let new_refresh_token = jwt::generate_opaque_token(32);
+    let refresh_expires_at = Utc::now() + Duration::days(jwt::REFRESH_TOKEN_TTL_DAYS);
+
+    let (user_id, sid, scope) = db::rotate_refresh_token(
+        &pool,
+        &jwt::hash_refresh_token(body.refresh_token.trim()),
+        &jwt::hash_refresh_token(&new_refresh_token),
+        refresh_expires_at,
+    )
+    .await
+    .map_err(warp::reject::custom)?;
+
+    // Refresh token kích hoạt lại session; session đã bị xóa → thu hồi luôn family
+    let resumed = session::store().resume(&sid)
+        .await
+        .map_err(warp::reject::custom)?;
+    if !resumed {
+        db::revoke_refresh_family(&pool, &sid)
+            .await
+            .map_err(warp::reject::custom)?;
+        return Err(warp::reject::custom(ApiError::Unauthorized("Session revoked".into())));
+    }
+
+    // Đọc lại user để token mới mang role hiện tại
+    let user = db::get_user_by_id(&pool, user_id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::Unauthorized("User not found".into())))?;
+
+    let granted = scopes::grant(user.role, scope.as_deref());
+    let token = jwt::create_token(user.id, &user.name, user.role, &granted, &sid)
+        .map_err(|_| warp::reject::custom(ApiError::InternalError("JWT creation failed".into())))?;
+
+    Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({
+        "message": "Token refreshed",
+        "token": token,
+        "token_type": "Bearer",
+        "expires_in": jwt::ACCESS_TOKEN_TTL_MINS * 60,
+        "refresh_token": new_refresh_token
+    })), StatusCode::OK))
+}
+
+/// Logout handler: thu hồi access token hiện tại và refresh token của phiên
+pub async fn logout_handler(pool: PgPool, claims: jwt::Claims) -> Result<impl warp::Reply, warp::Rejection> {
+    revocation::revoke_token(&pool, &claims)
+        .await
+        .map_err(warp::reject::custom)?;
+    db::revoke_refresh_family(&pool, &claims.sid)
+        .await
+        .map_err(warp::reject::custom)?;
+    session::store().remove(&claims.sid)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Logged out" })),
+        StatusCode::OK
+    ))
+}
+
+/// Logout all handler: thu hồi mọi token của user trên tất cả thiết bị
+pub async fn logout_all_handler(pool: PgPool, claims: jwt::Claims) -> Result<impl warp::Reply, warp::Rejection> {
+    revocation::revoke_token(&pool, &claims)
+        .await
+        .map_err(warp::reject::custom)?;
+    revocation::revoke_all_for_user(&pool, claims.sub)
+        .await
+        .map_err(warp::reject::custom)?;
+    session::store().remove_user(claims.sub)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Logged out from all devices" })),
+        StatusCode::OK
+    ))
+}
+
+/// List sessions handler: liệt kê các phiên đăng nhập của user
+pub async fn list_sessions_handler(id: i32, claims: jwt::Claims) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let sessions = session::store().list(id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let resp: Vec<SessionResponse> = sessions
+        .into_iter()
+        .map(|s| SessionResponse {
+            current: s.id == claims.sid,
+            id: s.id,
+            created_at: s.created_at,
+            last_activity: s.last_activity,
+            ip: s.ip,
+            user_agent: s.user_agent,
+        })
+        .collect();
+
+    Ok(warp::reply::with_status(warp::reply::json(&resp), StatusCode::OK))
+}
+
+/// Revoke session handler: hủy một phiên đăng nhập (và refresh token của phiên đó)
+pub async fn revoke_session_handler(
+    id: i32,
+    sid: String,
+    pool: PgPool,
+    claims: jwt::Claims,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let store = session::store();
+    match store.get(&sid).await.map_err(warp::reject::custom)? {
+        Some(s) if s.user_id == id => {}
+        _ => return Err(warp::reject::custom(ApiError::NotFound)),
+    }
+
+    store.remove(&sid)
+        .await
+        .map_err(warp::reject::custom)?;
+    db::revoke_refresh_family(&pool, &sid)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Session revoked" })),
+        StatusCode::OK
+    ))
+}
+
+/// Create API key handler: tạo API key cho client máy (key chỉ trả về một lần)
+pub async fn create_api_key_handler(
+    id: i32,
+    body: CreateApiKeyRequest,
+    pool: PgPool,
+    claims: jwt::Claims,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(warp::reject::custom(ApiError::BadRequest("Name cannot be empty".into())));
+    }
+
+    // Key chỉ được mang scope mà token hiện tại đang có. Mặc định bỏ các scope quản lý key/phiên;
+    // caller xác thực bằng API key thì không được cấp chúng kể cả khi yêu cầu rõ.
+    let scope = match body.scopes {
+        Some(requested) => {
+            if requested.is_empty() {
+                return Err(warp::reject::custom(ApiError::BadRequest("Scopes cannot be empty".into())));
+            }
+            if let Some(missing) = requested.iter().find(|s| !claims.has_scope(s)) {
+                return Err(warp::reject::custom(ApiError::Forbidden(format!("Scope {} is not granted to this token", missing))));
+            }
+            if claims.is_api_key()
+                && let Some(restricted) = requested.iter().find(|s| scopes::API_KEY_RESTRICTED.contains(&s.as_str()))
+            {
+                return Err(warp::reject::custom(ApiError::Forbidden(format!("Scope {} cannot be granted by an API key", restricted))));
+            }
+            requested.join(" ")
+        }
+        None => claims
+            .scope
+            .split_whitespace()
+            .filter(|s| !scopes::API_KEY_RESTRICTED.contains(s))
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    if scope.is_empty() {
+        return Err(warp::reject::custom(ApiError::BadRequest("No scopes of this token can be granted to an API key".into())));
+    }
+
+    let (prefix, secret, key) = api_keys::generate();
+    let key_hash = db::hash_password(&secret)
+        .map_err(|e| warp::reject::custom(ApiError::InternalError(e.to_string())))?;
+
+    let api_key = db::create_api_key(&pool, id, name, &prefix, &key_hash, &scope)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let resp = ApiKeyResponse { key: Some(key), ..api_key.into() };
+    Ok(warp::reply::with_status(warp::reply::json(&resp), StatusCode::CREATED))
+}
+
+/// List API keys handler: liệt kê API key của user (không có key/hash)
+pub async fn list_api_keys_handler(id: i32, pool: PgPool, claims: jwt::Claims) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let keys = db::list_api_keys(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?;
+    let resp: Vec<ApiKeyResponse> = keys.into_iter().map(ApiKeyResponse::from).collect();
+
+    Ok(warp::reply::with_status(warp::reply::json(&resp), StatusCode::OK))
+}
+
+/// Revoke API key handler
+pub async fn revoke_api_key_handler(
+    id: i32,
+    key_id: i32,
+    pool: PgPool,
+    claims: jwt::Claims,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let revoked = db::revoke_api_key(&pool, id, key_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    if !revoked {
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+    api_keys::forget(key_id);
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "API key revoked" })),
+        StatusCode::OK
+    ))
+}
+
+/// Số user tối đa mỗi trang của GET /users
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// List users handler (admin): phân trang theo cursor, lọc theo tên / ngày tạo
+pub async fn list_users_handler(query: UserListQuery, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let limit = query.limit.unwrap_or(50);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(warp::reject::custom(ApiError::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE))));
+    }
+    let sort = match query.sort.as_deref() {
+        Some(s) => s.parse::<UserSort>().map_err(|e| warp::reject::custom(ApiError::BadRequest(e)))?,
+        None => UserSort::default(),
+    };
+    let cursor = match query.cursor.as_deref() {
+        Some(c) => Some(UserCursor::decode(c).ok_or_else(|| warp::reject::custom(ApiError::BadRequest("Invalid cursor".into())))?),
+        None => None,
+    };
+
+    // Lấy dư 1 dòng để biết còn trang sau hay không
+    let mut users = db::list_users(
+        &pool,
+        query.name_prefix.as_deref().filter(|p| !p.is_empty()),
+        query.created_after,
+        sort,
+        cursor.as_ref(),
+        limit + 1,
+    )
+    .await
+    .map_err(warp::reject::custom)?;
+
+    let next_cursor = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|u| UserCursor { created_at: u.created_at, id: u.id }.encode())
+    } else {
+        None
+    };
+
+    let resp = UserListResponse {
+        users: users.into_iter().map(UserResponse::from).collect(),
+        next_cursor,
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&resp), StatusCode::OK))
+}
+
+/// Audit log handler (admin): lọc theo user / action / thời điểm, mới nhất trước
+pub async fn list_audit_events_handler(query: AuditQuery, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let limit = query.limit.unwrap_or(50);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(warp::reject::custom(ApiError::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE))));
+    }
+
+    // Lấy dư 1 dòng để biết còn trang sau hay không
+    let mut events = db::list_audit_events(
+        &pool,
+        query.user,
+        query.action.as_deref().filter(|a| !a.is_empty()),
+        query.since,
+        query.cursor,
+        limit + 1,
+    )
+    .await
+    .map_err(warp::reject::custom)?;
+
+    let next_cursor = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|e| e.id)
+    } else {
+        None
+    };
+
+    let resp = AuditListResponse { events, next_cursor };
+    Ok(warp::reply::with_status(warp::reply::json(&resp), StatusCode::OK))
+}
+
+/// Get user handler: xem hồ sơ của mình (hoặc của người khác nếu có scope users:read)
+pub async fn get_user_handler(id: i32, pool: PgPool, claims: jwt::Claims) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id && !claims.has_scope(scopes::USERS_READ) {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let user = db::get_user_by_id(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))?;
+
+    let resp = ProfileResponse {
+        has_avatar: user.avatar_path.is_some(),
+        user: user.into(),
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&resp), StatusCode::OK))
+}
+
+/// Update user handler: đổi tên và thông tin hồ sơ
+pub async fn update_user_handler(
+    id: i32,
+    body: UpdateUserRequest,
+    pool: PgPool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let name = body.name.as_deref().map(str::trim);
+    if name.is_some_and(str::is_empty) {
+        return Err(warp::reject::custom(ApiError::BadRequest("Name cannot be empty".into())));
+    }
+    let display_name = body.display_name.as_deref().map(str::trim);
+    if display_name.is_some_and(|d| d.chars().count() > 100) {
+        return Err(warp::reject::custom(ApiError::BadRequest("Display name must be at most 100 chars".into())));
+    }
+    let bio = body.bio.as_deref().map(str::trim);
+    if bio.is_some_and(|b| b.chars().count() > 500) {
+        return Err(warp::reject::custom(ApiError::BadRequest("Bio must be at most 500 chars".into())));
+    }
+    let email = body.email.as_deref().map(str::trim);
+    if let Some(e) = email
+        && !e.is_empty()
+    {
+        check_email(e)?;
+    }
+
+    let user = db::update_user_profile(&pool, id, name, display_name, bio, email)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))?;
+
+    // Email mới → gửi mã xác thực
+    if email.is_some_and(|e| !e.is_empty()) && user.email_verified_at.is_none() {
+        send_email_verification(&pool, &user).await?;
+    }
+
+    Ok(warp::reply::with_status(warp::reply::json(&UserResponse::from(user)), StatusCode::OK))
+}
+
+/// Change password handler: đổi mật khẩu (cần mật khẩu hiện tại), thu hồi mọi phiên
+pub async fn change_password_handler(
+    id: i32,
+    body: ChangePasswordRequest,
+    pool: PgPool,
+    claims: jwt::Claims,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let user = db::get_user_by_id(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))?;
+    let verified = db::verify_password(&user.password_hash, &body.current_password)
+        .map_err(|_| warp::reject::custom(ApiError::InternalError("Password verification failed".into())))?;
+    if !verified {
+        return Err(warp::reject::custom(ApiError::Unauthorized("Incorrect password".into())));
+    }
+    check_new_password(&body.new_password, &user.name)?;
+
+    set_password(&pool, id, &body.new_password).await?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Password changed, please log in again" })),
+        StatusCode::OK
+    ))
+}
+
+/// Forgot password handler: gửi link đặt lại mật khẩu qua email.
+/// Luôn trả về cùng một response để không lộ email nào đã đăng ký.
+pub async fn forgot_password_handler(body: ForgotPasswordRequest, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let email = body.email.trim();
+    if email.is_empty() {
+        return Err(warp::reject::custom(ApiError::BadRequest("Email cannot be empty".into())));
+    }
+
+    if let Some(user) = db::get_user_by_email(&pool, email).await.map_err(warp::reject::custom)? {
+        let token = jwt::generate_opaque_token(32);
+        let expires_at = Utc::now() + Duration::minutes(CONFIG.password_reset_ttl_mins);
+        db::create_password_reset_token(&pool, user.id, &jwt::hash_refresh_token(&token), expires_at)
+            .await
+            .map_err(warp::reject::custom)?;
+
+        let mail = Email {
+            to: user.email.unwrap_or_default(),
+            subject: "Reset your password".into(),
+            body: format!(
+                "Hi {},\n\nUse this link to reset your password (valid for {} minutes):\n{}/reset-password?token={}\n",
+                user.name, CONFIG.password_reset_ttl_mins, CONFIG.app_base_url, token
+            ),
+        };
+        if let Err(e) = mailer::mailer().send(&mail).await {
+            tracing::warn!("Password reset email failed: {}", e);
+        }
+    }
+
+    if let Err(e) = db::purge_password_reset_tokens(&pool).await {
+        tracing::warn!("Reset token purge failed: {}", e);
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "If the email is registered, a reset link has been sent" })),
+        StatusCode::OK
+    ))
+}
+
+/// Reset password handler: đặt mật khẩu mới bằng token từ email (dùng một lần)
+pub async fn reset_password_handler(body: ResetPasswordRequest, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let token_hash = jwt::hash_refresh_token(body.token.trim());
+    let invalid_token = || warp::reject::custom(ApiError::BadRequest("Invalid or expired reset token".into()));
+
+    // Kiểm tra policy trước khi dùng token, để mật khẩu không hợp lệ không làm mất token
+    let user_id = db::get_password_reset_token_user(&pool, &token_hash)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(invalid_token)?;
+    let user = db::get_user_by_id(&pool, user_id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(invalid_token)?;
+    check_new_password(&body.new_password, &user.name)?;
+
+    let user_id = db::consume_password_reset_token(&pool, &token_hash)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(invalid_token)?;
+
+    set_password(&pool, user_id, &body.new_password).await?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Password has been reset, please log in again" })),
+        StatusCode::OK
+    ))
+}
+
+/// Request email verification handler: gửi (lại) mã xác thực email
+pub async fn request_email_verification_handler(
+    id: i32,
+    pool: PgPool,
+    claims: jwt::Claims,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let user = db::get_user_by_id(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))?;
+    if user.email_verified_at.is_some() {
+        return Err(warp::reject::custom(ApiError::BadRequest("Email already verified".into())));
+    }
+
+    send_email_verification(&pool, &user).await?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Verification code sent" })),
+        StatusCode::OK
+    ))
+}
+
+/// Verify email handler: xác nhận email bằng mã đã gửi
+pub async fn verify_email_handler(body: VerifyEmailRequest, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let verified = db::verify_email_token(&pool, &jwt::hash_refresh_token(body.code.trim()))
+        .await
+        .map_err(warp::reject::custom)?;
+    if !verified {
+        return Err(warp::reject::custom(ApiError::BadRequest("Invalid or expired verification code".into())));
+    }
+
+    if let Err(e) = db::purge_email_verification_tokens(&pool).await {
+        tracing::warn!("Verification token purge failed: {}", e);
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Email verified" })),
+        StatusCode::OK
+    ))
+}
+
+/// 2FA setup handler: tạo secret TOTP mới (chưa có hiệu lực cho tới khi enable)
+pub async fn setup_totp_handler(id: i32, pool: PgPool, claims: jwt::Claims) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let secret = totp::generate_secret();
+    let rows = db::set_totp_secret(&pool, id, &secret)
+        .await
+        .map_err(warp::reject::custom)?;
+    if rows == 0 {
+        return Err(warp::reject::custom(ApiError::BadRequest("2FA is already enabled".into())));
+    }
+
+    Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({
+        "secret": secret,
+        "otpauth_uri": totp::otpauth_uri(&CONFIG.totp_issuer, &claims.name, &secret)
+    })), StatusCode::OK))
+}
+
+/// 2FA enable handler: xác nhận secret bằng mã TOTP, trả về mã khôi phục (chỉ một lần)
+pub async fn enable_totp_handler(
+    id: i32,
+    body: TotpCodeRequest,
+    pool: PgPool,
+    claims: jwt::Claims,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let user = db::get_user_by_id(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))?;
+    if user.totp_enabled_at.is_some() {
+        return Err(warp::reject::custom(ApiError::BadRequest("2FA is already enabled".into())));
+    }
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or_else(|| warp::reject::custom(ApiError::BadRequest("Call 2fa/setup first".into())))?;
+    let step = totp::verify(secret, &body.code, None)
+        .ok_or_else(|| warp::reject::custom(ApiError::BadRequest("Invalid TOTP code".into())))?;
+
+    let codes: Vec<String> = (0..totp::RECOVERY_CODE_COUNT).map(|_| totp::generate_recovery_code()).collect();
+    let hashes: Vec<String> = codes
+        .iter()
+        .map(|c| jwt::hash_refresh_token(&totp::normalize_recovery_code(c)))
+        .collect();
+
+    let enabled = db::enable_totp(&pool, id, step, &hashes)
+        .await
+        .map_err(warp::reject::custom)?;
+    if !enabled {
+        return Err(warp::reject::custom(ApiError::BadRequest("2FA is already enabled".into())));
+    }
+
+    Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({
+        "message": "2FA enabled",
+        "recovery_codes": codes
+    })), StatusCode::OK))
+}
+
+/// Unlock user handler (admin): mở khóa tài khoản bị khóa do login sai nhiều lần
+pub async fn unlock_user_handler(id: i32, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let rows = db::reset_login_failures(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?;
+    if rows == 0 {
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "User unlocked" })),
+        StatusCode::OK
+    ))
+}
+
 /// Delete user handler
-pub async fn delete_user_handler(id: i32, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+pub async fn delete_user_handler(
+    id: i32,
+    pool: PgPool,
+    claims: crate::jwt::Claims,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
     let rows = db::delete_user(&pool, id)
         .await
         .map_err(warp::reject::custom)?;
@@ -85,9 +989,77 @@ pub async fn delete_user_handler(id: i32, pool: PgPool) -> Result<impl warp::Rep
     if rows == 0 {
         return Err(warp::reject::custom(ApiError::NotFound));
     }
+    // Xóa mềm: thu hồi mọi token / phiên ngay, dữ liệu giữ lại tới hết thời gian ân hạn
+    revocation::revoke_all_for_user(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?;
+    session::store().remove_user(id)
+        .await
+        .map_err(warp::reject::custom)?;
+    api_keys::forget_user(id);
+    audit::record_now(
+        &pool,
+        audit::USER_DELETE,
+        Some(claims.sub),
+        Some(id),
+        &audit::Client::new(client_ip, user_agent),
+        serde_json::json!({}),
+    )
+    .await;
 
     Ok(warp::reply::with_status(
-        warp::reply::json(&serde_json::json!({ "message": "User deleted successfully" })),
+        warp::reply::json(&serde_json::json!({
+            "message": "User deleted successfully",
+            "restorable_until": Utc::now() + Duration::days(CONFIG.user_delete_grace_days)
+        })),
+        StatusCode::OK
+    ))
+}
+
+/// Restore user handler (admin): khôi phục user đã xóa trong thời gian ân hạn
+pub async fn restore_user_handler(id: i32, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let rows = db::restore_user(&pool, id, CONFIG.user_delete_grace_secs())
+        .await
+        .map_err(warp::reject::custom)?;
+    if rows == 0 {
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "User restored" })),
+        StatusCode::OK
+    ))
+}
+
+/// Set role handler (admin): đổi role của user và thu hồi token cũ của user đó
+pub async fn set_role_handler(
+    id: i32,
+    body: RoleRequest,
+    pool: PgPool,
+    claims: jwt::Claims,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub == id {
+        return Err(warp::reject::custom(ApiError::BadRequest("You cannot change your own role".into())));
+    }
+
+    let rows = db::update_user_role(&pool, id, body.role)
+        .await
+        .map_err(warp::reject::custom)?;
+    if rows == 0 {
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+
+    // Token cũ vẫn mang role cũ → buộc user đăng nhập lại
+    revocation::revoke_all_for_user(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?;
+    session::store().remove_user(id)
+        .await
+        .map_err(warp::reject::custom)?;
+    api_keys::forget_user(id);
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "message": "Role updated", "role": body.role })),
         StatusCode::OK
     ))
 }
@@ -97,6 +1069,8 @@ pub async fn upload_avatar_handler(
     id: i32,
     pool: PgPool,
     claims: crate::jwt::Claims,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
     mut form: warp::multipart::FormData,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     if claims.sub != id {
@@ -142,7 +1116,19 @@ pub async fn upload_avatar_handler(
     }
 
     let saved_path = saved_path.ok_or_else(|| warp::reject::custom(ApiError::BadRequest("No 'avatar' file found".into())))?;
-    db::update_user_avatar(&pool, id, &saved_path).await.map_err(warp::reject::custom)?;
+    let rows = db::update_user_avatar(&pool, id, &saved_path).await.map_err(warp::reject::custom)?;
+    if rows == 0 {
+        let _ = tokio::fs::remove_file(&saved_path).await;
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+    audit::record(
+        &pool,
+        audit::AVATAR_UPDATE,
+        Some(claims.sub),
+        Some(id),
+        &audit::Client::new(client_ip, user_agent),
+        serde_json::json!({ "path": saved_path }),
+    );
 
     Ok(warp::reply::with_status(
         warp::reply::json(&AvatarResponse { path: saved_path }),
@@ -150,6 +1136,128 @@ pub async fn upload_avatar_handler(
     ))
 }
 
+/// Export handler: xuất toàn bộ dữ liệu cá nhân của user thành file ZIP
+pub async fn export_user_handler(id: i32, pool: PgPool, claims: jwt::Claims) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != id {
+        return Err(warp::reject::custom(ApiError::NotAllowed));
+    }
+
+    let user = db::get_user_by_id(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))?;
+    let sessions: Vec<SessionResponse> = session::store().list(id)
+        .await
+        .map_err(warp::reject::custom)?
+        .into_iter()
+        .map(|s| SessionResponse {
+            current: s.id == claims.sid,
+            id: s.id,
+            created_at: s.created_at,
+            last_activity: s.last_activity,
+            ip: s.ip,
+            user_agent: s.user_agent,
+        })
+        .collect();
+    let api_keys: Vec<ApiKeyResponse> = db::list_api_keys(&pool, id)
+        .await
+        .map_err(warp::reject::custom)?
+        .into_iter()
+        .map(ApiKeyResponse::from)
+        .collect();
+    let avatar_path = db::get_avatar_path(&pool, id).await.map_err(warp::reject::custom)?;
+
+    let header = serde_json::json!({
+        "exported_at": Utc::now(),
+        "profile": ProfileResponse { has_avatar: user.avatar_path.is_some(), user: user.into() },
+        "sessions": sessions,
+        "api_keys": api_keys,
+    });
+
+    // Gói ZIP được ghi trong spawn_blocking (nén tốn CPU) và stream dần xuống client;
+    // lịch sử audit được đọc theo trang, tối đa export::MAX_AUDIT_EVENTS sự kiện mới nhất
+    let (writer, body) = export::body_channel();
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let abort = writer.abort_handle();
+        let mut zip = ZipWriter::new_stream(writer);
+        if let Err(e) = write_export(&mut zip, &runtime, &pool, id, header, avatar_path).and_then(|()| zip.finish().map(drop)) {
+            tracing::warn!("Export for user {} aborted: {}", id, e);
+            abort.abort(std::io::Error::other(e.to_string()));
+        }
+    });
+
+    let mut resp = warp::http::Response::new(body);
+    let headers = resp.headers_mut();
+    headers.insert("content-type", warp::http::HeaderValue::from_static("application/zip"));
+    if let Ok(value) = warp::http::HeaderValue::from_str(&format!("attachment; filename=\"user_{}_export.zip\"", id)) {
+        headers.insert("content-disposition", value);
+    }
+    Ok(resp)
+}
+
+/// Ghi `export.json` (audit đọc theo trang từ DB) và file avatar vào gói xuất
+fn write_export(
+    zip: &mut ZipWriter<StreamWriter<BodyWriter>>,
+    runtime: &tokio::runtime::Handle,
+    pool: &PgPool,
+    id: i32,
+    header: serde_json::Value,
+    avatar_path: Option<String>,
+) -> ZipResult<()> {
+    use std::io::Write;
+
+    // export.json = các trường của `header` + "audit_events" (+ "audit_events_truncated" khi vượt giới hạn)
+    // Không biết trước kích thước export.json nên luôn ghi kèm trường ZIP64
+    zip.start_file("export.json", SimpleFileOptions::default().large_file(true))?;
+    let header = serde_json::to_string_pretty(&header).map_err(std::io::Error::from)?;
+    let header = header.trim_end().strip_suffix('}').unwrap_or(&header).trim_end();
+    write!(zip, "{},\n  \"audit_events\": [", header)?;
+
+    let mut before_id = None;
+    let mut written = 0;
+    let mut truncated = false;
+    loop {
+        let limit = export::AUDIT_PAGE_SIZE.min(export::MAX_AUDIT_EVENTS - written);
+        let page = runtime
+            .block_on(db::list_audit_events(pool, Some(id), None, None, before_id, limit))
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        for event in &page {
+            if written > 0 {
+                zip.write_all(b",")?;
+            }
+            write!(zip, "\n    {}", serde_json::to_string(event).map_err(std::io::Error::from)?)?;
+            written += 1;
+        }
+        if (page.len() as i64) < limit {
+            break;
+        }
+        if written >= export::MAX_AUDIT_EVENTS {
+            // Chỉ đánh dấu bị cắt khi thật sự còn sự kiện cũ hơn
+            let more = runtime
+                .block_on(db::list_audit_events(pool, Some(id), None, None, page.last().map(|e| e.id), 1))
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            truncated = !more.is_empty();
+            break;
+        }
+        before_id = page.last().map(|e| e.id);
+    }
+    write!(zip, "\n  ],\n  \"audit_events_truncated\": {}\n}}\n", truncated)?;
+
+    if let Some(path) = avatar_path {
+        match std::fs::File::open(&path) {
+            Ok(mut file) => {
+                let name = Path::new(&path).file_name().and_then(|n| n.to_str()).unwrap_or("avatar");
+                let large = file.metadata()?.len() >= u32::MAX as u64;
+                zip.start_file(format!("avatar/{}", name), SimpleFileOptions::default().large_file(large))?;
+                std::io::copy(&mut file, zip)?;
+            }
+            Err(e) => tracing::warn!("Export: cannot read avatar {}: {}", path, e),
+        }
+    }
+    Ok(())
+}
+
 /// Get avatar handler
 pub async fn get_avatar_handler(id: i32, pool: PgPool) -> Result<impl warp::Reply, warp::Rejection> {
     let avatar_path = db::get_avatar_path(&pool, id)
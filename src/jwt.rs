@@ -1,79 +1,117 @@
 use serde::{Serialize, Deserialize};
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey, errors::Error as JwtError, errors::ErrorKind};
+use jsonwebtoken::{encode, decode, decode_header, Header, Validation, errors::Error as JwtError, errors::ErrorKind};
 use chrono::{Utc, Duration};
-use std::env;
+use sha2::{Digest, Sha256};
+use rand_core::{OsRng, RngCore};
 
-use std::collections::HashMap;
-use tokio::sync::Mutex;
-
-use lazy_static::lazy_static;
+use crate::config::CONFIG;
+use crate::keys;
+use crate::models::Role;
+use crate::scopes;
+use crate::session::{self, SessionStatus};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: i32,
     pub name: String,
+    pub role: Role,
+    /// Các scope của token, phân cách bằng dấu cách (vd. "avatar:write sessions:read")
+    pub scope: String,
     pub exp: usize,
+    pub iat: usize,
+    /// ID duy nhất của token, dùng cho danh sách thu hồi
+    pub jti: String,
+    /// ID phiên đăng nhập (trùng với family của refresh token)
+    pub sid: String,
 }
 
-// Session timeout sau 30 phút không hoạt động
-pub const SESSION_TIMEOUT_SECS: i64 = 30*60;
+// Access token sống ngắn, client dùng refresh token để lấy token mới
+pub const ACCESS_TOKEN_TTL_MINS: i64 = 15;
+
+// Refresh token hết hạn sau 30 ngày
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+impl Claims {
+    /// Token có scope này không
+    pub fn has_scope(&self, scope: &str) -> bool {
+        scopes::contains(&self.scope, scope)
+    }
 
-// Lưu last activity cho mỗi user_id
-lazy_static! {
-    pub static ref LAST_ACTIVITY: Mutex<HashMap<i32, i64>> = Mutex::new(HashMap::new());
+    /// Claims được dựng từ API key (không phải access token)
+    pub fn is_api_key(&self) -> bool {
+        self.sid.starts_with("apikey:")
+    }
 }
 
-/// Tạo JWT token
-pub fn create_token(user_id: i32, username: &str) -> Result<String, JwtError> {
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
-    let exp = Utc::now()
-        .checked_add_signed(Duration::hours(24))
+/// Tạo JWT token (ký bằng khóa hiện tại, header có `kid`)
+pub fn create_token(user_id: i32, username: &str, role: Role, scope: &str, sid: &str) -> Result<String, JwtError> {
+    let key = keys::signing_key();
+    let now = Utc::now();
+    let exp = now
+        .checked_add_signed(Duration::minutes(ACCESS_TOKEN_TTL_MINS))
         .expect("valid timestamp")
         .timestamp() as usize;
 
     let claims = Claims {
         sub: user_id,
         name: username.to_string(),
+        role,
+        scope: scope.to_string(),
         exp,
+        iat: now.timestamp() as usize,
+        jti: generate_opaque_token(16),
+        sid: sid.to_string(),
     };
 
-    // Sau khi login, lưu lại thời gian hoạt động
-    tokio::spawn(async move {
-        let mut map = LAST_ACTIVITY.lock().await;
-        map.insert(user_id, Utc::now().timestamp());
-    });
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
 
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes()))
+    encode(&header, &claims, &key.encoding)
 }
 
 /// Xác thực token và kiểm tra session timeout
 pub async fn verify_token(token: &str) -> Result<Claims, String> {
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
+    let claims = decode_token(token)?;
+
+    // ---- CHECK SESSION TIMEOUT (theo từng session) ----
+    // Mỗi request thành công → store cập nhật last_activity của session
+    match session::store().touch(&claims.sid, CONFIG.session_timeout_secs).await {
+        Ok(SessionStatus::Active) => Ok(claims),
+        Ok(SessionStatus::Idle) => Err("Session expired due to inactivity".into()),
+        Ok(SessionStatus::Missing) => Err("Session not found".into()),
+        Err(_) => Err("Session check failed".into()),
+    }
+}
+
+/// Chỉ kiểm tra chữ ký (theo `kid`) và hạn của token, không chạm tới session
+pub fn decode_token(token: &str) -> Result<Claims, String> {
+    let header = decode_header(token).map_err(|_| "Token invalid".to_string())?;
+    let key = header
+        .kid
+        .as_deref()
+        .and_then(keys::find)
+        .ok_or_else(|| "Token invalid".to_string())?;
 
-    let claims = decode::<Claims>(
+    decode::<Claims>(
         token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default()
+        &key.decoding,
+        &Validation::new(key.algorithm)
     )
+    .map(|data| data.claims)
     .map_err(|err| match *err.kind() {
         ErrorKind::ExpiredSignature => "Token expired".to_string(),
         _ => "Token invalid".to_string(),
-    })?
-    .claims;
-
-    // ---- CHECK SESSION TIMEOUT ----
-    let mut map = LAST_ACTIVITY.lock().await;
-    let now = Utc::now().timestamp();
-
-    if let Some(last) = map.get(&claims.sub) {
-        if now - *last > SESSION_TIMEOUT_SECS {
-            map.remove(&claims.sub);
-            return Err("Session expired due to inactivity (30 minutes)".into());
-        }
-    }
+    })
+}
 
-    // Mỗi request thành công → server cập nhật last_activity
-    map.insert(claims.sub, now);
+/// Sinh chuỗi ngẫu nhiên (hex) dùng cho refresh token / token family
+pub fn generate_opaque_token(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
 
-    Ok(claims)
+/// Hash refresh token trước khi lưu DB (SHA-256, chỉ lưu hash)
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
@@ -0,0 +1,456 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, bail, Context};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding};
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use rand_core::OsRng;
+use sha2::{Digest, Sha256};
+
+use crate::config::CONFIG;
+use crate::jwt;
+
+/// Khóa ký JWT (kèm `kid` và public key dạng JWK)
+pub struct SigningKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+    /// Public key dạng JWK — None với HS256 (không công khai khóa đối xứng)
+    pub jwk: Option<Jwk>,
+}
+
+impl SigningKey {
+    /// Đọc private key PEM (Ed25519 PKCS#8 hoặc RSA PKCS#1/PKCS#8).
+    /// Nếu không có `kid` thì dùng JWK thumbprint (RFC 7638).
+    pub fn from_pem(pem_bytes: &[u8], kid: Option<String>) -> anyhow::Result<Self> {
+        let parsed = pem::parse(pem_bytes).context("Invalid PEM")?;
+
+        let (algorithm, encoding, params) = match parsed.tag() {
+            "PRIVATE KEY" => match Ed25519KeyPair::from_pkcs8_maybe_unchecked(parsed.contents()) {
+                Ok(pair) => (
+                    Algorithm::EdDSA,
+                    EncodingKey::from_ed_pem(pem_bytes)?,
+                    ed25519_params(pair.public_key().as_ref()),
+                ),
+                Err(_) => {
+                    let key = RsaPrivateKey::from_pkcs8_der(parsed.contents())
+                        .map_err(|e| anyhow!("Unsupported private key: {}", e))?;
+                    (Algorithm::RS256, EncodingKey::from_rsa_pem(pem_bytes)?, rsa_params(&key))
+                }
+            },
+            "RSA PRIVATE KEY" => {
+                let key = RsaPrivateKey::from_pkcs1_der(parsed.contents())
+                    .map_err(|e| anyhow!("Invalid RSA private key: {}", e))?;
+                (Algorithm::RS256, EncodingKey::from_rsa_pem(pem_bytes)?, rsa_params(&key))
+            }
+            other => bail!("Unsupported PEM block: {}", other),
+        };
+
+        let kid = kid.unwrap_or_else(|| thumbprint(&params));
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(match algorithm {
+                    Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+                    _ => KeyAlgorithm::RS256,
+                }),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: params,
+        };
+        let decoding = DecodingKey::from_jwk(&jwk)?;
+
+        Ok(SigningKey { kid, algorithm, encoding, decoding, jwk: Some(jwk) })
+    }
+
+    /// Khóa HS256 từ secret (chỉ dùng nội bộ, không công khai trong JWKS)
+    pub fn from_secret(secret: &str, kid: Option<String>) -> Self {
+        SigningKey {
+            kid: kid.unwrap_or_else(|| "hs256".to_string()),
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret.as_bytes()),
+            decoding: DecodingKey::from_secret(secret.as_bytes()),
+            jwk: None,
+        }
+    }
+}
+
+fn rsa_params(key: &RsaPrivateKey) -> AlgorithmParameters {
+    AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+        e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+    })
+}
+
+fn ed25519_params(public_key: &[u8]) -> AlgorithmParameters {
+    AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(public_key),
+    })
+}
+
+/// JWK thumbprint theo RFC 7638 (các member bắt buộc, sắp xếp theo thứ tự từ điển)
+fn thumbprint(params: &AlgorithmParameters) -> String {
+    let canonical = match params {
+        AlgorithmParameters::RSA(p) => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, p.e, p.n),
+        AlgorithmParameters::OctetKeyPair(p) => format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, p.x),
+        _ => String::new(),
+    };
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// Định dạng `kid` do `rotate-keys` tạo; thời điểm tạo khóa được đọc lại từ tên file chứ không từ
+/// mtime (copy, deploy hay restore backup đổi mtime, các replica sẽ chọn khóa ký khác nhau)
+const KID_FORMAT: &str = "k%Y%m%d%H%M%S";
+
+/// Thời điểm tạo khóa ghi trong `kid` (UTC)
+fn kid_created_at(kid: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(kid, KID_FORMAT).ok().map(|t| t.and_utc())
+}
+
+/// Khóa trong key set kèm thời điểm tạo (lấy từ `kid`)
+struct KeyEntry {
+    key: Arc<SigningKey>,
+    created_at: DateTime<Utc>,
+    path: Option<PathBuf>,
+}
+
+/// Tập khóa ký, sắp xếp từ cũ đến mới.
+/// Khóa mới được công khai trong JWKS một khoảng `activation_delay` trước khi dùng để ký,
+/// để mọi replica kịp nạp; khóa cũ vẫn được dùng để xác thực cho tới khi token cuối cùng ký bằng nó hết hạn.
+pub struct KeySet {
+    entries: Vec<KeyEntry>,
+    activation_delay: Duration,
+}
+
+impl KeySet {
+    fn activated_at(&self, idx: usize) -> DateTime<Utc> {
+        self.entries[idx].created_at + self.activation_delay
+    }
+
+    /// Vị trí khóa đang ký: khóa mới nhất đã qua thời gian chờ (hoặc khóa mới nhất nếu chưa có khóa nào)
+    fn signing_index(&self, now: DateTime<Utc>) -> usize {
+        (0..self.entries.len())
+            .rev()
+            .find(|&i| self.activated_at(i) <= now)
+            .unwrap_or(self.entries.len() - 1)
+    }
+
+    /// Các khóa còn được chấp nhận khi xác thực token
+    fn verifiable(&self, now: DateTime<Utc>) -> impl Iterator<Item = &KeyEntry> {
+        let signing = self.signing_index(now);
+        // Token sống tối đa ACCESS_TOKEN_TTL, cộng thêm leeway 60s của Validation
+        let max_token_age = Duration::minutes(jwt::ACCESS_TOKEN_TTL_MINS) + Duration::seconds(60);
+        self.entries.iter().enumerate().filter_map(move |(i, e)| {
+            let usable = i >= signing || now < self.activated_at(i + 1) + max_token_age;
+            usable.then_some(e)
+        })
+    }
+}
+
+static KEYS: RwLock<Option<Arc<KeySet>>> = RwLock::new(None);
+
+/// Nạp một khóa HS256 cố định cho unit test của các module khác (không đọc cấu hình)
+#[cfg(test)]
+pub fn init_for_tests() {
+    let key = SigningKey::from_secret("test-secret", None);
+    let set = KeySet {
+        entries: vec![KeyEntry { key: Arc::new(key), created_at: DateTime::UNIX_EPOCH, path: None }],
+        activation_delay: Duration::zero(),
+    };
+    *KEYS.write().expect("key set lock poisoned") = Some(Arc::new(set));
+}
+
+/// Đọc tập khóa theo cấu hình.
+/// Ưu tiên JWT_KEYS_DIR (nhiều khóa, hỗ trợ xoay vòng), rồi JWT_PRIVATE_KEY_PATH, sau đó JWT_SECRET (HS256).
+/// Không có khóa nào → lỗi.
+fn load_key_set() -> anyhow::Result<KeySet> {
+    let activation_delay = Duration::seconds(CONFIG.jwt_keys_reload_secs as i64);
+
+    let entries = if let Some(dir) = &CONFIG.jwt_keys_dir {
+        let entries = load_dir(Path::new(dir))?;
+        if entries.is_empty() {
+            bail!("JWT_KEYS_DIR {} contains no *.pem key; run `rotate-keys` to create one", dir);
+        }
+        entries
+    } else if let Some(path) = &CONFIG.jwt_private_key_path {
+        let pem_bytes = std::fs::read(path)
+            .with_context(|| format!("Cannot read JWT_PRIVATE_KEY_PATH {}", path))?;
+        let key = SigningKey::from_pem(&pem_bytes, CONFIG.jwt_key_id.clone())
+            .with_context(|| format!("Cannot load JWT private key {}", path))?;
+        vec![KeyEntry { key: Arc::new(key), created_at: DateTime::UNIX_EPOCH, path: None }]
+    } else if let Some(secret) = &CONFIG.jwt_secret {
+        tracing::warn!("Using HS256 with JWT_SECRET; other services cannot verify tokens via JWKS");
+        let key = SigningKey::from_secret(secret, CONFIG.jwt_key_id.clone());
+        vec![KeyEntry { key: Arc::new(key), created_at: DateTime::UNIX_EPOCH, path: None }]
+    } else {
+        bail!("No JWT signing key configured: set JWT_KEYS_DIR or JWT_PRIVATE_KEY_PATH (RS256/EdDSA), or JWT_SECRET (HS256)");
+    };
+
+    Ok(KeySet { entries, activation_delay })
+}
+
+/// Đọc mọi file `<kid>.pem` trong thư mục, sắp xếp theo thời điểm tạo ghi trong `kid`
+fn load_dir(dir: &Path) -> anyhow::Result<Vec<KeyEntry>> {
+    let mut entries = Vec::new();
+    for item in std::fs::read_dir(dir).with_context(|| format!("Cannot read JWT_KEYS_DIR {}", dir.display()))? {
+        let path = item?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("pem") {
+            continue;
+        }
+        let kid = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow!("Invalid key file name {}", path.display()))?
+            .to_string();
+        let created_at = kid_created_at(&kid).ok_or_else(|| {
+            anyhow!("Key file {} must be named k<YYYYmmddHHMMSS>.pem (use `rotate-keys`)", path.display())
+        })?;
+        let pem_bytes = std::fs::read(&path)?;
+        let key = SigningKey::from_pem(&pem_bytes, Some(kid))
+            .with_context(|| format!("Cannot load JWT private key {}", path.display()))?;
+        entries.push(KeyEntry { key: Arc::new(key), created_at, path: Some(path) });
+    }
+    entries.sort_by_key(|e| e.created_at);
+    Ok(entries)
+}
+
+/// Nạp (hoặc nạp lại) khóa ký từ cấu hình.
+/// Gọi trong main khi khởi động (lỗi → server không chạy) và định kỳ để nhận khóa mới sau khi xoay vòng.
+pub fn init() -> anyhow::Result<()> {
+    let set = load_key_set()?;
+    let kids: Vec<&str> = set.entries.iter().map(|e| e.key.kid.as_str()).collect();
+
+    let mut guard = KEYS.write().expect("key set lock poisoned");
+    let changed = guard
+        .as_ref()
+        .is_none_or(|old| old.entries.iter().map(|e| e.key.kid.as_str()).ne(kids.iter().copied()));
+    if changed {
+        tracing::info!("JWT keys loaded: {:?}, newest alg={:?}", kids, set.entries[set.entries.len() - 1].key.algorithm);
+    }
+    *guard = Some(Arc::new(set));
+    Ok(())
+}
+
+fn current() -> Arc<KeySet> {
+    KEYS.read()
+        .expect("key set lock poisoned")
+        .clone()
+        .expect("signing keys not initialized")
+}
+
+/// Khóa dùng để ký token mới (luôn là khóa mới nhất đã kích hoạt)
+pub fn signing_key() -> Arc<SigningKey> {
+    let set = current();
+    set.entries[set.signing_index(Utc::now())].key.clone()
+}
+
+/// Tìm khóa xác thực theo `kid` trong header của token (bỏ qua khóa đã hết hạn xác thực)
+pub fn find(kid: &str) -> Option<Arc<SigningKey>> {
+    current()
+        .verifiable(Utc::now())
+        .find(|e| e.key.kid == kid)
+        .map(|e| e.key.clone())
+}
+
+/// Tập public key cho endpoint JWKS (gồm khóa sắp kích hoạt và khóa cũ còn hiệu lực xác thực)
+pub fn jwks() -> JwkSet {
+    JwkSet {
+        keys: current()
+            .verifiable(Utc::now())
+            .filter_map(|e| e.key.jwk.clone())
+            .collect(),
+    }
+}
+
+/// Tạo khóa mới trong JWT_KEYS_DIR (lệnh `rotate-keys`) và xóa các khóa đã hết hạn xác thực.
+/// Trả về `kid` của khóa mới.
+pub fn rotate(algorithm: Option<&str>) -> anyhow::Result<String> {
+    let dir = CONFIG
+        .jwt_keys_dir
+        .as_ref()
+        .context("JWT_KEYS_DIR must be set to rotate signing keys")?;
+    let dir = Path::new(dir);
+    std::fs::create_dir_all(dir)?;
+
+    let existing = load_dir(dir)?;
+    let algorithm = match algorithm {
+        Some("EdDSA") => Algorithm::EdDSA,
+        Some("RS256") => Algorithm::RS256,
+        Some(other) => bail!("Unsupported algorithm {} (use EdDSA or RS256)", other),
+        None => existing.last().map(|e| e.key.algorithm).unwrap_or(Algorithm::EdDSA),
+    };
+
+    let pem_string = match algorithm {
+        Algorithm::RS256 => RsaPrivateKey::new(&mut OsRng, 2048)?
+            .to_pkcs8_pem(LineEnding::LF)?
+            .to_string(),
+        _ => {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+                .map_err(|_| anyhow!("Ed25519 key generation failed"))?;
+            pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref()))
+        }
+    };
+
+    let kid = Utc::now().format(KID_FORMAT).to_string();
+    let path = dir.join(format!("{}.pem", kid));
+    if path.exists() {
+        bail!("Key {} already exists", path.display());
+    }
+    std::fs::write(&path, pem_string)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    // Xóa file của các khóa không còn token nào hợp lệ
+    let set = KeySet {
+        entries: load_dir(dir)?,
+        activation_delay: Duration::seconds(CONFIG.jwt_keys_reload_secs as i64),
+    };
+    let keep: Vec<String> = set.verifiable(Utc::now()).map(|e| e.key.kid.clone()).collect();
+    for entry in set.entries.iter().filter(|e| !keep.contains(&e.key.kid)) {
+        if let Some(p) = &entry.path {
+            std::fs::remove_file(p)?;
+            tracing::info!("Removed expired signing key {}", entry.key.kid);
+        }
+    }
+
+    Ok(kid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_set(created: &[DateTime<Utc>]) -> KeySet {
+        KeySet {
+            entries: created
+                .iter()
+                .enumerate()
+                .map(|(i, &created_at)| KeyEntry {
+                    key: Arc::new(SigningKey::from_secret("secret", Some(format!("k{}", i)))),
+                    created_at,
+                    path: None,
+                })
+                .collect(),
+            activation_delay: Duration::minutes(5),
+        }
+    }
+
+    fn verifiable_kids(set: &KeySet, now: DateTime<Utc>) -> Vec<String> {
+        set.verifiable(now).map(|e| e.key.kid.clone()).collect()
+    }
+
+    fn max_token_age() -> Duration {
+        Duration::minutes(jwt::ACCESS_TOKEN_TTL_MINS) + Duration::seconds(60)
+    }
+
+    /// Thư mục tạm chứa các khóa Ed25519 `<kid>.pem` với mtime cho trước
+    fn key_dir(name: &str, keys: &[(&str, DateTime<Utc>)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("keys-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (kid, mtime) in keys {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+            let path = dir.join(format!("{}.pem", kid));
+            std::fs::write(&path, pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref()))).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified((*mtime).into())
+                .unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn load_dir_orders_by_kid_not_mtime() {
+        let now = Utc::now();
+        // mtime ngược với thứ tự tạo (vd. sau khi copy không giữ mtime)
+        let dir = key_dir("order", &[
+            ("k20250101000000", now),
+            ("k20260101000000", now - Duration::days(30)),
+            ("k20240101000000", now - Duration::minutes(1)),
+        ]);
+
+        let entries = load_dir(&dir).unwrap();
+        let kids: Vec<&str> = entries.iter().map(|e| e.key.kid.as_str()).collect();
+        assert_eq!(kids, ["k20240101000000", "k20250101000000", "k20260101000000"]);
+        assert_eq!(entries[2].created_at, kid_created_at("k20260101000000").unwrap());
+        assert_eq!(entries[2].created_at.to_rfc3339(), "2026-01-01T00:00:00+00:00");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_dir_rejects_unknown_kid_format() {
+        let dir = key_dir("badname", &[("k20250101000000", Utc::now()), ("mykey", Utc::now())]);
+        assert!(load_dir(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn single_key_signs_before_activation() {
+        let t0 = Utc::now();
+        let set = key_set(&[t0]);
+        assert_eq!(set.signing_index(t0), 0);
+        assert_eq!(verifiable_kids(&set, t0), ["k0"]);
+    }
+
+    #[test]
+    fn new_key_is_published_before_it_signs() {
+        let t0 = Utc::now() - Duration::days(1);
+        let t1 = Utc::now();
+        let set = key_set(&[t0, t1]);
+
+        let before = t1 + Duration::minutes(5) - Duration::seconds(1);
+        assert_eq!(set.signing_index(before), 0);
+        assert_eq!(verifiable_kids(&set, before), ["k0", "k1"]);
+
+        let activated = t1 + Duration::minutes(5);
+        assert_eq!(set.signing_index(activated), 1);
+        assert_eq!(verifiable_kids(&set, activated), ["k0", "k1"]);
+    }
+
+    #[test]
+    fn old_key_expires_after_last_token_it_signed() {
+        let t0 = Utc::now() - Duration::days(1);
+        let t1 = Utc::now();
+        let set = key_set(&[t0, t1]);
+        let retired = t1 + Duration::minutes(5) + max_token_age();
+
+        assert_eq!(verifiable_kids(&set, retired - Duration::seconds(1)), ["k0", "k1"]);
+        assert_eq!(verifiable_kids(&set, retired), ["k1"]);
+    }
+
+    #[test]
+    fn skipped_key_expires_with_its_successor() {
+        // k1 bị thay bởi k2 trước khi kịp kích hoạt; mỗi khóa cũ hết hạn theo mốc kích hoạt của khóa kế tiếp
+        let t0 = Utc::now() - Duration::days(1);
+        let t1 = Utc::now();
+        let t2 = t1 + Duration::minutes(1);
+        let set = key_set(&[t0, t1, t2]);
+
+        let now = t2 + Duration::minutes(5);
+        assert_eq!(set.signing_index(now), 2);
+        assert_eq!(verifiable_kids(&set, now), ["k0", "k1", "k2"]);
+        assert_eq!(verifiable_kids(&set, t2 + Duration::minutes(5) + max_token_age()), ["k2"]);
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::{Arc, OnceLock};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::CONFIG;
+use crate::errors::ApiError;
+
+/// Một email cần gửi
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Nơi gửi email (link đặt lại mật khẩu, mã xác thực...)
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), ApiError>;
+}
+
+/// In email ra stdout — dùng khi phát triển local
+pub struct StdoutMailer;
+
+#[async_trait]
+impl Mailer for StdoutMailer {
+    async fn send(&self, email: &Email) -> Result<(), ApiError> {
+        println!("{}", format_email(email));
+        Ok(())
+    }
+}
+
+/// Ghi nối email vào file — dùng khi phát triển local / test thủ công
+pub struct FileMailer {
+    path: String,
+}
+
+impl FileMailer {
+    pub fn new(path: String) -> Self {
+        FileMailer { path }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), ApiError> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| ApiError::InternalError(format!("Mailer file error: {}", e)))?;
+        file.write_all(format_email(email).as_bytes())
+            .await
+            .map_err(|e| ApiError::InternalError(format!("Mailer write error: {}", e)))?;
+        Ok(())
+    }
+}
+
+fn format_email(email: &Email) -> String {
+    format!(
+        "---- {} ----\nTo: {}\nSubject: {}\n\n{}\n\n",
+        Utc::now().to_rfc3339(),
+        email.to,
+        email.subject,
+        email.body
+    )
+}
+
+static MAILER: OnceLock<Arc<dyn Mailer>> = OnceLock::new();
+
+/// Khởi tạo mailer theo cấu hình MAILER (gọi một lần trong main)
+pub fn init() -> anyhow::Result<()> {
+    let mailer: Arc<dyn Mailer> = match CONFIG.mailer.as_str() {
+        "stdout" => Arc::new(StdoutMailer),
+        "file" => Arc::new(FileMailer::new(CONFIG.mailer_file_path.clone())),
+        other => anyhow::bail!("Unknown MAILER: {}", other),
+    };
+    MAILER
+        .set(mailer)
+        .map_err(|_| anyhow::anyhow!("Mailer already initialized"))
+}
+
+/// Mailer đang dùng
+pub fn mailer() -> Arc<dyn Mailer> {
+    MAILER.get().expect("mailer not initialized").clone()
+}
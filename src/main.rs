@@ -1,26 +1,154 @@
 
+mod config;
 mod db;
 mod handlers;
 mod models;
 mod routes;
 mod errors;
 mod jwt;
+mod keys;
 mod rate_limit;
+mod rate_limit_algorithms;
+mod rate_limit_backend;
+mod revocation;
+mod scopes;
+mod session;
+mod api_keys;
+mod mailer;
+mod totp;
+mod password_policy;
+mod export;
+mod audit;
+mod client_ip;
 
 use sqlx::PgPool;
-use tracing_subscriber;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
     tracing_subscriber::fmt::init();
 
+    // Lệnh quản trị: xoay vòng khóa ký JWT (`local_server_API rotate-keys [EdDSA|RS256]`)
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("rotate-keys") {
+        let kid = keys::rotate(args.get(2).map(String::as_str))?;
+        println!("Created signing key {}", kid);
+        return Ok(());
+    }
+
+    // Kiểm tra tham số Argon2 trong config
+    db::argon2_params()?;
+
+    // Nạp danh sách mật khẩu phổ biến (PASSWORD_COMMON_LIST_PATH)
+    password_policy::init()?;
+
+    // Nạp các policy rate limit (RATE_LIMIT_POLICIES)
+    rate_limit::init()?;
+
+    // Nạp danh sách proxy tin cậy (TRUSTED_PROXIES)
+    client_ip::init()?;
+
+    // Nạp khóa ký JWT — thiếu khóa thì dừng ngay, không dùng secret mặc định
+    keys::init()?;
+
+    // Nạp lại định kỳ để nhận khóa mới sau khi xoay vòng
+    if config::CONFIG.jwt_keys_dir.is_some() {
+        tokio::spawn(async {
+            let secs = config::CONFIG.jwt_keys_reload_secs.max(1);
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(secs));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = keys::init() {
+                    tracing::warn!("JWT key reload failed, keeping current keys: {:#}", e);
+                }
+            }
+        });
+    }
+
     let database_url = std::env::var("DATABASE_URL")?;
     let pool = PgPool::connect(&database_url).await?;
 
     // Chạy migration
     sqlx::migrate!("./migrations").run(&pool).await.expect("Migration failed");
 
+    // Lệnh quản trị: đổi role của user (`local_server_API set-role <name> <user|moderator|admin>`)
+    if args.get(1).map(String::as_str) == Some("set-role") {
+        let (Some(name), Some(role)) = (args.get(2), args.get(3)) else {
+            anyhow::bail!("Usage: set-role <name> <user|moderator|admin>");
+        };
+        let role: models::Role = role.parse().map_err(anyhow::Error::msg)?;
+        let rows = db::update_user_role_by_name(&pool, name, role).await?;
+        if rows == 0 {
+            anyhow::bail!("User {} not found", name);
+        }
+        println!("User {} is now {}", name, role);
+        return Ok(());
+    }
+
+    // Khởi tạo session store (memory / postgres)
+    session::init(pool.clone())?;
+
+    // Khởi tạo mailer (stdout / file)
+    mailer::init()?;
+
+    // Dọn định kỳ cache thu hồi token, để cache không phình ra theo số token đã kiểm tra
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            revocation::purge_expired();
+        }
+    });
+
+    // Dọn định kỳ các session không thể resume nữa (refresh token đã hết hạn)
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            let max_idle = jwt::REFRESH_TOKEN_TTL_DAYS * 24 * 60 * 60;
+            if let Err(e) = session::store().purge_stale(max_idle).await {
+                tracing::warn!("Session purge failed: {}", e);
+            }
+        }
+    });
+
+    // Xóa hẳn user đã xóa mềm quá thời gian ân hạn (kèm file avatar)
+    let purge_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match db::purge_deleted_users(&purge_pool, config::CONFIG.user_delete_grace_secs()).await {
+                Ok(avatars) => {
+                    for path in avatars.into_iter().flatten() {
+                        if let Err(e) = tokio::fs::remove_file(&path).await
+                            && e.kind() != std::io::ErrorKind::NotFound
+                        {
+                            tracing::warn!("Removing avatar {} failed: {}", path, e);
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("Deleted user purge failed: {}", e),
+            }
+        }
+    });
+
+    // Dọn state rate limit không còn hoạt động (bộ nhớ hoặc bảng rate_limit_state)
+    let limiter = rate_limit::RateLimiter::new(pool.clone())?;
+    let sweep_limiter = limiter.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(config::CONFIG.rate_limit_sweep_secs.max(1)));
+        loop {
+            interval.tick().await;
+            match sweep_limiter.sweep().await {
+                Ok(removed) if removed > 0 => tracing::debug!("Rate limiter swept {} idle keys", removed),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Rate limiter sweep failed: {}", e),
+            }
+        }
+    });
+
     let bind_address = std::env::var("BIND_ADDRESS").unwrap_or_else(|_| "127.0.0.1".into());
     let bind_port: u16 = std::env::var("BIND_PORT")
         .ok()
@@ -28,7 +156,7 @@ async fn main() -> anyhow::Result<()> {
         .unwrap_or(3030);
 
     // Tạo routes từ module routes
-    let routes = routes::create_routes(pool);
+    let routes = routes::create_routes(pool, limiter);
 
     println!("Server running on http://{}:{}", bind_address, bind_port);
 
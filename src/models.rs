@@ -1,11 +1,72 @@
 use serde::{Deserialize, Serialize};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+// Vai trò của user, xếp theo quyền tăng dần (user < moderator < admin)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// User trong DB (bảng users)
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+    pub password_hash: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub email: Option<String>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub avatar_path: Option<String>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    pub totp_last_step: Option<i64>,
+}
 
 // Request body cho /register
 #[derive(Deserialize, Debug)]
 pub struct RegisterRequest {
     pub name: String,
     pub password: String,
+    pub email: Option<String>,
 }
 
 // Request body cho /login
@@ -15,15 +76,319 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+// Request body cho POST /users/{id}/password
+#[derive(Deserialize, Debug)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+// Request body cho POST /password/forgot
+#[derive(Deserialize, Debug)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+// Request body cho POST /password/reset
+#[derive(Deserialize, Debug)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+// Request body cho POST /verify-email
+#[derive(Deserialize, Debug)]
+pub struct VerifyEmailRequest {
+    pub code: String,
+}
+
+// Request body cho POST /users/{id}/2fa/enable
+#[derive(Deserialize, Debug)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+// Request body cho POST /login/mfa (code = mã TOTP hoặc mã khôi phục)
+#[derive(Deserialize, Debug)]
+pub struct MfaLoginRequest {
+    pub challenge_token: String,
+    pub code: String,
+}
+
+// Request body cho /token/refresh
+#[derive(Deserialize, Debug)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+// Request body cho /token/scoped
+#[derive(Deserialize, Debug)]
+pub struct ScopedTokenRequest {
+    pub scopes: Vec<String>,
+}
+
+// Request body cho PUT /users/{id}/role
+#[derive(Deserialize, Debug)]
+pub struct RoleRequest {
+    pub role: Role,
+}
+
 // Response cho /register
 #[derive(Serialize)]
 pub struct UserResponse {
     pub id: i32,
     pub name: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub email: Option<String>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub two_factor_enabled: bool,
+}
+
+impl From<User> for UserResponse {
+    fn from(u: User) -> Self {
+        UserResponse {
+            id: u.id,
+            name: u.name,
+            role: u.role,
+            created_at: u.created_at,
+            display_name: u.display_name,
+            bio: u.bio,
+            email: u.email,
+            email_verified_at: u.email_verified_at,
+            two_factor_enabled: u.totp_enabled_at.is_some(),
+        }
+    }
+}
+
+// Response cho GET /users/{id}
+#[derive(Serialize)]
+pub struct ProfileResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub has_avatar: bool,
+}
+
+// Request body cho PATCH /users/{id} (bỏ trống = giữ nguyên, "" = xóa)
+#[derive(Deserialize, Debug)]
+pub struct UpdateUserRequest {
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub email: Option<String>,
+}
+
+// Thứ tự sắp xếp cho GET /users (`id`, `-id`, `created_at`, `-created_at`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UserSort {
+    #[default]
+    IdAsc,
+    IdDesc,
+    CreatedAsc,
+    CreatedDesc,
+}
+
+impl FromStr for UserSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(UserSort::IdAsc),
+            "-id" => Ok(UserSort::IdDesc),
+            "created_at" => Ok(UserSort::CreatedAsc),
+            "-created_at" => Ok(UserSort::CreatedDesc),
+            other => Err(format!("Unknown sort: {}", other)),
+        }
+    }
+}
+
+// Vị trí của dòng cuối trang trước (keyset pagination)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserCursor {
     pub created_at: DateTime<Utc>,
+    pub id: i32,
+}
+
+impl UserCursor {
+    /// Mã hóa cursor thành chuỗi opaque cho client
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.created_at.timestamp_micros(), self.id))
+    }
+
+    /// Giải mã cursor từ query string
+    pub fn decode(s: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(s).ok()?).ok()?;
+        let (micros, id) = raw.split_once(':')?;
+        Some(UserCursor {
+            created_at: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+// Query string cho GET /users
+#[derive(Deserialize, Debug)]
+pub struct UserListQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub name_prefix: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub sort: Option<String>,
+}
+
+// Một trang kết quả của GET /users
+#[derive(Serialize)]
+pub struct UserListResponse {
+    pub users: Vec<UserResponse>,
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct AvatarResponse {
     pub path: String,
-}
\ No newline at end of file
+}
+
+// Phiên đăng nhập (bảng sessions)
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+// Response cho GET /users/{id}/sessions
+#[derive(Serialize)]
+pub struct SessionResponse {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Session của chính token đang gọi API
+    pub current: bool,
+}
+
+// API key cá nhân (bảng api_keys)
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scope: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+// Request body cho POST /users/{id}/api-keys
+#[derive(Deserialize, Debug)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Bỏ trống = toàn bộ scope của token đang gọi
+    pub scopes: Option<Vec<String>>,
+}
+
+// Response cho API key (không bao giờ trả về hash)
+#[derive(Serialize)]
+pub struct ApiKeyResponse {
+    pub id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scope: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Key đầy đủ, chỉ trả về một lần khi tạo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(k: ApiKey) -> Self {
+        ApiKeyResponse {
+            id: k.id,
+            name: k.name,
+            prefix: k.prefix,
+            scope: k.scope,
+            created_at: k.created_at,
+            last_used_at: k.last_used_at,
+            revoked_at: k.revoked_at,
+            key: None,
+        }
+    }
+}
+
+// Sự kiện audit (bảng audit_events)
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub actor_id: Option<i32>,
+    pub target_user_id: Option<i32>,
+    pub action: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+// Query string cho GET /admin/audit
+#[derive(Deserialize, Debug)]
+pub struct AuditQuery {
+    /// Lọc theo user (actor hoặc target)
+    pub user: Option<i32>,
+    pub action: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    /// `next_cursor` của trang trước
+    pub cursor: Option<i64>,
+}
+
+// Một trang kết quả của GET /admin/audit (mới nhất trước)
+#[derive(Serialize)]
+pub struct AuditListResponse {
+    pub events: Vec<AuditEvent>,
+    pub next_cursor: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_cursor_round_trips() {
+        let cursor = UserCursor {
+            created_at: DateTime::from_timestamp_micros(1_760_000_000_123_456).unwrap(),
+            id: 42,
+        };
+        assert_eq!(UserCursor::decode(&cursor.encode()), Some(cursor));
+    }
+
+    #[test]
+    fn user_cursor_keeps_microseconds_and_negative_ids() {
+        let cursor = UserCursor {
+            created_at: DateTime::from_timestamp_micros(-1).unwrap(),
+            id: -7,
+        };
+        assert_eq!(UserCursor::decode(&cursor.encode()), Some(cursor));
+    }
+
+    #[test]
+    fn user_cursor_rejects_malformed_input() {
+        let encode = |raw: &str| URL_SAFE_NO_PAD.encode(raw);
+        assert_eq!(UserCursor::decode(""), None);
+        assert_eq!(UserCursor::decode("not base64!"), None);
+        assert_eq!(UserCursor::decode(&URL_SAFE_NO_PAD.encode([0xff, 0xfe])), None);
+        assert_eq!(UserCursor::decode(&encode("1760000000")), None);
+        assert_eq!(UserCursor::decode(&encode("abc:1")), None);
+        assert_eq!(UserCursor::decode(&encode("1760000000:x")), None);
+        assert_eq!(UserCursor::decode(&encode("1760000000:99999999999")), None);
+        assert_eq!(UserCursor::decode(&encode(&format!("{}:1", i64::MAX))), None);
+    }
+}
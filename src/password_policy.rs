@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use crate::config::{env_opt, env_or, CONFIG};
+use crate::errors::ApiError;
+
+/// Tên quá ngắn (vd. "a") sẽ khớp với gần như mọi mật khẩu nên không áp dụng quy tắc tên đăng nhập
+const MIN_USERNAME_CHECK_LEN: usize = 3;
+
+/// Quy tắc mật khẩu (register / đổi / đặt lại mật khẩu)
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Cấm mật khẩu chứa tên đăng nhập (chỉ với tên từ MIN_USERNAME_CHECK_LEN ký tự)
+    pub forbid_username: bool,
+    /// File danh sách mật khẩu phổ biến (mỗi dòng một mật khẩu)
+    pub common_passwords_path: Option<String>,
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Self {
+        PasswordPolicy {
+            min_length: env_or("PASSWORD_MIN_LENGTH", 8),
+            max_length: env_or("PASSWORD_MAX_LENGTH", 128),
+            require_lowercase: env_or("PASSWORD_REQUIRE_LOWERCASE", false),
+            require_uppercase: env_or("PASSWORD_REQUIRE_UPPERCASE", false),
+            require_digit: env_or("PASSWORD_REQUIRE_DIGIT", false),
+            require_symbol: env_or("PASSWORD_REQUIRE_SYMBOL", false),
+            forbid_username: env_or("PASSWORD_FORBID_USERNAME", true),
+            common_passwords_path: env_opt("PASSWORD_COMMON_LIST_PATH"),
+        }
+    }
+
+    /// Kiểm tra mật khẩu, trả về mọi quy tắc bị vi phạm (rỗng = hợp lệ)
+    pub fn violations(&self, password: &str, username: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        let len = password.chars().count();
+
+        if len < self.min_length {
+            errors.push(format!("Password must be at least {} chars", self.min_length));
+        }
+        if len > self.max_length {
+            errors.push(format!("Password must be at most {} chars", self.max_length));
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            errors.push("Password must contain a lowercase letter".into());
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            errors.push("Password must contain an uppercase letter".into());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            errors.push("Password must contain a digit".into());
+        }
+        if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+            errors.push("Password must contain a symbol".into());
+        }
+
+        let lower = password.to_lowercase();
+        let username = username.trim().to_lowercase();
+        if self.forbid_username && username.chars().count() >= MIN_USERNAME_CHECK_LEN && lower.contains(&username) {
+            errors.push("Password must not contain the username".into());
+        }
+        if COMMON_PASSWORDS.get().is_some_and(|list| list.contains(&lower)) {
+            errors.push("Password is too common".into());
+        }
+        errors
+    }
+}
+
+/// Danh sách mật khẩu phổ biến (chữ thường), nạp một lần từ PASSWORD_COMMON_LIST_PATH
+static COMMON_PASSWORDS: OnceLock<HashSet<String>> = OnceLock::new();
+
+/// Nạp danh sách mật khẩu phổ biến khi khởi động (gọi một lần trong main)
+pub fn init() -> anyhow::Result<()> {
+    let mut list = HashSet::new();
+    if let Some(path) = CONFIG.password_policy.common_passwords_path.as_deref() {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read common password list {}: {}", path, e))?;
+        list.extend(
+            content
+                .lines()
+                .map(|l| l.trim().to_lowercase())
+                .filter(|l| !l.is_empty()),
+        );
+        tracing::info!("Loaded {} common passwords from {}", list.len(), path);
+    }
+    COMMON_PASSWORDS
+        .set(list)
+        .map_err(|_| anyhow::anyhow!("Common password list already loaded"))
+}
+
+/// Kiểm tra mật khẩu mới theo policy trong CONFIG
+pub fn check(password: &str, username: &str) -> Result<(), ApiError> {
+    let violations = CONFIG.password_policy.violations(password, username);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::BadRequestDetails("Password does not meet the policy".into(), violations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Policy chỉ bật đúng một quy tắc (ngoài giới hạn độ dài rộng)
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 0,
+            max_length: usize::MAX,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            forbid_username: false,
+            common_passwords_path: None,
+        }
+    }
+
+    #[test]
+    fn min_length_counts_chars() {
+        let policy = PasswordPolicy { min_length: 8, ..policy() };
+        assert_eq!(policy.violations("short", "bob"), vec!["Password must be at least 8 chars"]);
+        assert!(policy.violations("mậtkhẩuu", "bob").is_empty());
+    }
+
+    #[test]
+    fn max_length() {
+        let policy = PasswordPolicy { max_length: 4, ..policy() };
+        assert_eq!(policy.violations("toolong", "bob"), vec!["Password must be at most 4 chars"]);
+        assert!(policy.violations("okay", "bob").is_empty());
+    }
+
+    #[test]
+    fn require_lowercase() {
+        let policy = PasswordPolicy { require_lowercase: true, ..policy() };
+        assert_eq!(policy.violations("UPPER123", "bob"), vec!["Password must contain a lowercase letter"]);
+        assert!(policy.violations("UPPEr123", "bob").is_empty());
+    }
+
+    #[test]
+    fn require_uppercase() {
+        let policy = PasswordPolicy { require_uppercase: true, ..policy() };
+        assert_eq!(policy.violations("lower123", "bob"), vec!["Password must contain an uppercase letter"]);
+        assert!(policy.violations("Lower123", "bob").is_empty());
+    }
+
+    #[test]
+    fn require_digit() {
+        let policy = PasswordPolicy { require_digit: true, ..policy() };
+        assert_eq!(policy.violations("NoDigits!", "bob"), vec!["Password must contain a digit"]);
+        assert!(policy.violations("OneDigit1", "bob").is_empty());
+    }
+
+    #[test]
+    fn require_symbol() {
+        let policy = PasswordPolicy { require_symbol: true, ..policy() };
+        assert_eq!(policy.violations("NoSymbol1", "bob"), vec!["Password must contain a symbol"]);
+        assert!(policy.violations("Symbol 1", "bob").is_empty());
+        assert!(policy.violations("Symbol#1", "bob").is_empty());
+    }
+
+    #[test]
+    fn forbid_username_is_case_insensitive() {
+        let policy = PasswordPolicy { forbid_username: true, ..policy() };
+        assert_eq!(policy.violations("xxBoBxx", " bob "), vec!["Password must not contain the username"]);
+        assert!(policy.violations("xxalicexx", "bob").is_empty());
+    }
+
+    #[test]
+    fn forbid_username_skips_short_names() {
+        let policy = PasswordPolicy { forbid_username: true, ..policy() };
+        assert!(policy.violations("a-secret", "a").is_empty());
+        assert!(policy.violations("ab-secret", "ab").is_empty());
+        assert!(policy.violations("xx", "").is_empty());
+        assert_eq!(policy.violations("abc-secret", "abc"), vec!["Password must not contain the username"]);
+    }
+
+    #[test]
+    fn common_passwords_are_rejected() {
+        COMMON_PASSWORDS.get_or_init(|| HashSet::from(["password123".to_string()]));
+        assert_eq!(policy().violations("PassWord123", "bob"), vec!["Password is too common"]);
+        assert!(policy().violations("password1234", "bob").is_empty());
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..policy()
+        };
+        assert_eq!(policy.violations("abc", "bob").len(), 4);
+    }
+}
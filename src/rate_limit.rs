@@ -1,80 +1,378 @@
-use std::time::{Duration, SystemTime};
-use dashmap::DashMap;
-use warp::{Filter, reject};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sqlx::PgPool;
+use warp::http::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use warp::{Filter, Reply, reject};
+use crate::api_keys;
+use crate::client_ip::client_ip;
+use crate::config::CONFIG;
 use crate::errors::ApiError;
-use tokio::sync::Mutex;
-use std::sync::Arc;
+use crate::jwt;
+use crate::rate_limit_algorithms::AlgorithmKind;
+use crate::rate_limit_backend::{MemoryRateLimitBackend, PgRateLimitBackend, RateLimitBackend};
 
-/// Số request tối đa trong WINDOW
-const MAX_REQUESTS: usize = 3; // bạn có thể điều chỉnh
-/// Kích thước sliding window (giây)
-const WINDOW: Duration = Duration::from_secs(60);
+/// Policy mặc định, RATE_LIMIT_POLICIES có thể ghi đè từng policy hoặc thêm policy mới
+const DEFAULT_POLICIES: &str = "auth=5/60:ip:sliding_log,default=60/60:user:sliding_window,avatar=300/60:ip:token_bucket";
+
+/// Request được đếm theo đâu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// Địa chỉ IP của client
+    Ip,
+    /// User đã xác thực (chung cho mọi token / API key của user); credential không hợp lệ → theo IP
+    User,
+    /// Từng API key đã xác thực; request không dùng API key hợp lệ → theo IP
+    ApiKey,
+}
+
+impl FromStr for RateLimitKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(RateLimitKey::Ip),
+            "user" => Ok(RateLimitKey::User),
+            "api_key" => Ok(RateLimitKey::ApiKey),
+            other => Err(format!("unknown key '{}' (expected ip, user or api_key)", other)),
+        }
+    }
+}
+
+/// Một policy có tên: tối đa `max_requests` request trong `window` cho mỗi key
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    pub name: String,
+    pub max_requests: usize,
+    pub window: Duration,
+    pub key: RateLimitKey,
+    pub algorithm: AlgorithmKind,
+}
+
+impl RateLimitPolicy {
+    /// Parse `<max_requests>/<window_secs>[:<key>[:<algorithm>]]`, ví dụ `5/60:ip:gcra`
+    pub fn parse(name: &str, spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(':').map(str::trim);
+        let limit = parts.next().unwrap_or_default();
+        let key = parts.next().map_or(Ok(RateLimitKey::Ip), str::parse)?;
+        let algorithm = parts.next().map_or(Ok(AlgorithmKind::SlidingLog), str::parse)?;
+        if parts.next().is_some() {
+            return Err(format!("too many fields in '{}'", spec));
+        }
+        let (max, window) = limit
+            .split_once('/')
+            .ok_or_else(|| format!("expected <max_requests>/<window_secs>, got '{}'", spec))?;
+        let max_requests: usize = max.trim().parse().map_err(|_| format!("invalid max_requests '{}'", max))?;
+        let window_secs: u64 = window.trim().parse().map_err(|_| format!("invalid window_secs '{}'", window))?;
+        if max_requests == 0 || window_secs == 0 {
+            return Err("max_requests and window_secs must be greater than 0".into());
+        }
+        Ok(RateLimitPolicy {
+            name: name.to_string(),
+            max_requests,
+            window: Duration::from_secs(window_secs),
+            key,
+            algorithm,
+        })
+    }
+}
+
+/// Các policy đã nạp, theo tên
+static POLICIES: OnceLock<HashMap<String, RateLimitPolicy>> = OnceLock::new();
+
+/// Parse danh sách `name=spec,name=spec` vào `policies`
+fn parse_policies(list: &str, policies: &mut HashMap<String, RateLimitPolicy>) -> anyhow::Result<()> {
+    for item in list.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let (name, spec) = item
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid rate limit policy '{}': expected name=spec", item))?;
+        let name = name.trim();
+        let policy = RateLimitPolicy::parse(name, spec.trim())
+            .map_err(|e| anyhow::anyhow!("Invalid rate limit policy '{}': {}", name, e))?;
+        policies.insert(name.to_string(), policy);
+    }
+    Ok(())
+}
+
+/// Nạp policy mặc định + RATE_LIMIT_POLICIES (gọi một lần trong main)
+pub fn init() -> anyhow::Result<()> {
+    let mut policies = HashMap::new();
+    parse_policies(DEFAULT_POLICIES, &mut policies)?;
+    if let Some(list) = CONFIG.rate_limit_policies.as_deref() {
+        parse_policies(list, &mut policies)?;
+    }
+    for p in policies.values() {
+        tracing::info!(
+            "Rate limit policy {}: {} requests / {}s per {:?} ({:?})",
+            p.name, p.max_requests, p.window.as_secs(), p.key, p.algorithm
+        );
+    }
+    POLICIES
+        .set(policies)
+        .map_err(|_| anyhow::anyhow!("Rate limit policies already loaded"))
+}
+
+/// Lấy policy theo tên (tên dùng trong routes luôn có trong DEFAULT_POLICIES)
+pub fn policy(name: &str) -> RateLimitPolicy {
+    find_policy(name)
+        .unwrap_or_else(|| panic!("unknown rate limit policy {}", name))
+        .clone()
+}
+
+/// Tìm policy theo tên
+pub fn find_policy(name: &str) -> Option<&'static RateLimitPolicy> {
+    POLICIES.get().expect("rate limit policies not initialized").get(name)
+}
+
+/// Trạng thái quota của một key, trả về qua header `RateLimit-*`
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    pub limit: usize,
+    pub remaining: usize,
+    /// Số giây tới khi request cũ nhất trong window hết hạn (có thêm quota)
+    pub reset_secs: u64,
+}
+
+/// Thời điểm hiện tại (giây Unix) cho các thuật toán
+fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
 
 #[derive(Clone)]
 pub struct RateLimiter {
-    /// Key: ip (String) -> value: Arc<Mutex<Vec<SystemTime>>> (danh sách các timestamp request gần đây)
-    pub store: Arc<DashMap<String, Arc<Mutex<Vec<SystemTime>>>>>,
+    /// Nơi lưu state (RATE_LIMIT_BACKEND)
+    backend: Arc<dyn RateLimitBackend>,
 }
 
 impl RateLimiter {
-    pub fn new() -> Self {
-        RateLimiter {
-            store: Arc::new(DashMap::new()),
-        }
+    /// Tạo limiter với backend theo cấu hình RATE_LIMIT_BACKEND
+    pub fn new(pool: PgPool) -> anyhow::Result<Self> {
+        let backend: Arc<dyn RateLimitBackend> = match CONFIG.rate_limit_backend.as_str() {
+            "memory" => Arc::new(MemoryRateLimitBackend::default()),
+            "postgres" => Arc::new(PgRateLimitBackend::new(pool)),
+            other => anyhow::bail!("Unknown RATE_LIMIT_BACKEND: {}", other),
+        };
+        Ok(RateLimiter { backend })
     }
 
-    /// Kiểm tra rate limit cho ip; trả Err(ApiError) nếu vượt
-    pub async fn check(&self, ip: String) -> Result<(), ApiError> {
-        let now = SystemTime::now();
+    /// Xác định key đếm request theo policy. Limiter chạy trước with_auth nên chỉ làm phần rẻ:
+    /// access token được kiểm tra chữ ký và hạn (không chạm session), API key chỉ được nhận ra khi
+    /// đã có trong cache xác thực (không chạy Argon2). Credential không xác thực được → đếm theo IP,
+    /// nên token giả hay prefix ngẫu nhiên không tạo được bucket mới.
+    pub fn identify(&self, policy: &RateLimitPolicy, client_ip: Option<IpAddr>, auth_header: Option<&str>) -> String {
+        let ip = || {
+            format!("ip:{}", client_ip.map(|ip| ip.to_string()).unwrap_or_else(|| "unknown".into()))
+        };
+        let auth_header = auth_header.map(str::trim).unwrap_or_default();
+        let api_key = || {
+            auth_header
+                .strip_prefix("ApiKey ")
+                .and_then(|key| api_keys::cached_identity(key.trim()))
+        };
 
-        // Lấy entry (hoặc insert mới với vec rỗng)
-        let entry = self.store.entry(ip.clone())
-            .or_insert_with(|| Arc::new(Mutex::new(Vec::new())))
-            .clone();
+        match policy.key {
+            RateLimitKey::Ip => ip(),
+            RateLimitKey::User => match auth_header.strip_prefix("Bearer ") {
+                Some(token) => jwt::decode_token(token.trim())
+                    .map(|claims| format!("user:{}", claims.sub))
+                    .unwrap_or_else(|_| ip()),
+                None => api_key().map(|(user_id, _)| format!("user:{}", user_id)).unwrap_or_else(ip),
+            },
+            RateLimitKey::ApiKey => api_key().map(|(_, key_id)| format!("apikey:{}", key_id)).unwrap_or_else(ip),
+        }
+    }
 
-        // Lock danh sách timestamp
-        let mut guard = entry.lock().await;
-        let timestamps = &mut *guard;
+    /// Chạy một thao tác của backend với timeout. Backend lỗi hoặc chậm (vd. DB quá tải) → None,
+    /// request được cho đi qua (fail open) để sự cố của limiter không làm hỏng mọi route.
+    async fn with_timeout<T>(&self, what: &str, fut: impl Future<Output = Result<T, ApiError>>) -> Option<T> {
+        let timeout = Duration::from_millis(CONFIG.rate_limit_backend_timeout_ms);
+        match tokio::time::timeout(timeout, fut).await {
+            Ok(Ok(value)) => Some(value),
+            Ok(Err(e)) => {
+                tracing::warn!("Rate limit {} failed, failing open: {}", what, e);
+                None
+            }
+            Err(_) => {
+                tracing::warn!("Rate limit {} timed out after {:?}, failing open", what, timeout);
+                None
+            }
+        }
+    }
 
-        // Loại bỏ timestamp cũ hơn WINDOW
-        let window_start = now.checked_sub(WINDOW).unwrap_or(SystemTime::UNIX_EPOCH);
-        // retain only timestamps >= window_start
-        timestamps.retain(|&t| t >= window_start);
+    /// Tính một request cho key theo policy; trả quota còn lại, Err(ApiError) nếu vượt
+    pub async fn check(&self, policy: &RateLimitPolicy, key: String) -> Result<RateLimitStatus, ApiError> {
+        let store_key = format!("{}|{}", policy.name, key);
+        let result = self
+            .with_timeout("check", self.backend.acquire(policy, &store_key, now_secs()))
+            .await;
 
-        // Nếu đã đạt giới hạn thì reject
-        if timestamps.len() >= MAX_REQUESTS {
-            return Err(ApiError::BadRequest(format!(
-                "Too many requests from {}. Only {} requests per {} seconds allowed.",
-                ip, MAX_REQUESTS, WINDOW.as_secs()
-            )));
+        let too_many = |retry_after_secs| ApiError::TooManyRequests {
+            limit: policy.max_requests,
+            window_secs: policy.window.as_secs(),
+            retry_after_secs,
+        };
+        match result {
+            Some(Ok(status)) => {
+                tracing::debug!(policy = %policy.name, remaining = status.remaining, "rate limit check passed");
+                Ok(status)
+            }
+            Some(Err(retry_after)) => Err(too_many(retry_after)),
+            // Backend lỗi hoặc quá hạn → cho qua, báo quota đầy
+            None => Ok(RateLimitStatus {
+                limit: policy.max_requests,
+                remaining: policy.max_requests,
+                reset_secs: 0,
+            }),
         }
+    }
 
-        // Thêm timestamp hiện tại
-        timestamps.push(now);
-
-        // (Tuỳ chọn) log cho dev
-        println!("[RateLimit][sliding] IP: {}, count_in_window: {}", ip, timestamps.len());
+    /// Bỏ các key không còn ảnh hưởng tới quota (gọi định kỳ trong main)
+    pub async fn sweep(&self) -> Result<u64, ApiError> {
+        self.backend.sweep(now_secs()).await
+    }
 
-        Ok(())
+    /// Gắn limiter với một policy để dùng trong routes
+    pub fn policy(&self, name: &str) -> RouteLimit {
+        RouteLimit {
+            limiter: self.clone(),
+            policy: Arc::new(policy(name)),
+        }
     }
 }
 
-/// Warp filter để sử dụng trong routes.
-pub fn with_rate_limit(
+/// Policy rate limit gắn vào một nhóm route
+#[derive(Clone)]
+pub struct RouteLimit {
     limiter: RateLimiter,
-) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
-    warp::addr::remote()
-        .and_then(move |addr: Option<std::net::SocketAddr>| {
-            let limiter = limiter.clone();
-            async move {
-                let ip = addr
-                    .map(|a| a.ip().to_string())
-                    .unwrap_or_else(|| "unknown".into());
+    policy: Arc<RateLimitPolicy>,
+}
 
-                limiter.check(ip).await.map_err(|e| reject::custom(e))?;
+impl RouteLimit {
+    /// Xác định key đếm request từ IP thật của client và header Authorization
+    fn identity(&self) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone + use<> {
+        let limiter = self.limiter.clone();
+        let policy = self.policy.clone();
 
-                Ok::<(), warp::Rejection>(())
-            }
+        client_ip()
+            .and(warp::header::optional::<String>("authorization"))
+            .map(move |client_ip: Option<IpAddr>, auth_header: Option<String>| {
+                limiter.identify(&policy, client_ip, auth_header.as_deref())
+            })
+    }
+
+    /// Filter đếm request, từ chối bằng 429 khi vượt quota. Quota còn lại được trả về để
+    /// `respond` gắn vào response: `.and(limit.check())` ngay sau path + method.
+    pub fn check(&self) -> impl Filter<Extract = (RateLimitStatus,), Error = warp::Rejection> + Clone + use<> {
+        let limiter = self.limiter.clone();
+        let policy = self.policy.clone();
+
+        self.identity().and_then(move |key: String| {
+            let limiter = limiter.clone();
+            let policy = policy.clone();
+            async move { limiter.check(&policy, key).await.map_err(reject::custom) }
         })
-        .untuple_one()
+    }
+}
+
+/// Header IETF `RateLimit-Limit` / `RateLimit-Remaining` / `RateLimit-Reset`
+fn set_headers(resp: &mut warp::reply::Response, limit: usize, remaining: usize, reset_secs: u64) {
+    let headers = resp.headers_mut();
+    for (name, value) in [
+        ("ratelimit-limit", limit as u64),
+        ("ratelimit-remaining", remaining as u64),
+        ("ratelimit-reset", reset_secs),
+    ] {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+}
+
+/// Chạy handler rồi gắn header RateLimit-* của lần đếm ở `check()` vào response thành công
+pub async fn respond<R: Reply>(
+    status: RateLimitStatus,
+    handler: impl Future<Output = Result<R, warp::Rejection>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let mut resp = handler.await?.into_response();
+    set_headers(&mut resp, status.limit, status.remaining, status.reset_secs);
+    Ok(resp)
+}
+
+/// Gắn `Retry-After` và RateLimit-* vào response 429
+pub fn add_rejection_headers(resp: &mut warp::reply::Response, error: &ApiError) {
+    if let ApiError::TooManyRequests { limit, retry_after_secs, .. } = *error {
+        resp.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        set_headers(resp, limit, 0, retry_after_secs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use crate::models::Role;
+    use crate::rate_limit_backend::MemoryRateLimitBackend;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter { backend: Arc::new(MemoryRateLimitBackend::default()) }
+    }
+
+    fn user_policy() -> RateLimitPolicy {
+        RateLimitPolicy::parse("default", "10/60:user").unwrap()
+    }
+
+    #[test]
+    fn parse_policy_fields() {
+        let policy = RateLimitPolicy::parse("p", "5/60:api_key:gcra").unwrap();
+        assert_eq!((policy.max_requests, policy.window.as_secs()), (5, 60));
+        assert_eq!(policy.key, RateLimitKey::ApiKey);
+        assert_eq!(policy.algorithm, AlgorithmKind::Gcra);
+        assert!(RateLimitPolicy::parse("p", "0/60").is_err());
+        assert!(RateLimitPolicy::parse("p", "5/60:ip:gcra:extra").is_err());
+    }
+
+    #[tokio::test]
+    async fn tokens_of_the_same_user_share_a_bucket() {
+        keys::init_for_tests();
+        let limiter = limiter();
+        let policy = user_policy();
+        let ip: Option<IpAddr> = "10.0.0.1".parse().ok();
+        let first = jwt::create_token(7, "bob", Role::User, "profile:write", "s1").unwrap();
+        let second = jwt::create_token(7, "bob", Role::User, "avatar:write", "s2").unwrap();
+
+        let key_a = limiter.identify(&policy, ip, Some(&format!("Bearer {}", first)));
+        let key_b = limiter.identify(&policy, ip, Some(&format!("Bearer {}", second)));
+        assert_eq!(key_a, "user:7");
+        assert_eq!(key_a, key_b);
+
+        let a = limiter.check(&policy, key_a).await.unwrap();
+        let b = limiter.check(&policy, key_b).await.unwrap();
+        assert_eq!((a.remaining, b.remaining), (9, 8));
+    }
+
+    #[test]
+    fn invalid_credentials_fall_back_to_ip() {
+        keys::init_for_tests();
+        let limiter = limiter();
+        let policy = user_policy();
+        let ip: Option<IpAddr> = "10.0.0.2".parse().ok();
+        let token = jwt::create_token(7, "bob", Role::User, "profile:write", "s1").unwrap();
+        let tampered = format!("{}x", token);
+
+        for header in [
+            "Bearer garbage".to_string(),
+            format!("Bearer {}", tampered),
+            "ApiKey lsk_randomprefix_secret".to_string(),
+            "Basic Ym9iOnB3".to_string(),
+        ] {
+            assert_eq!(limiter.identify(&policy, ip, Some(&header)), "ip:10.0.0.2", "{}", header);
+        }
+        assert_eq!(limiter.identify(&policy, ip, None), "ip:10.0.0.2");
+
+        let api_key_policy = RateLimitPolicy::parse("keys", "10/60:api_key").unwrap();
+        assert_eq!(limiter.identify(&api_key_policy, ip, Some("ApiKey lsk_abc_def")), "ip:10.0.0.2");
+    }
 }
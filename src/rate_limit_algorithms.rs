@@ -0,0 +1,358 @@
+use std::str::FromStr;
+
+use crate::rate_limit::{RateLimitPolicy, RateLimitStatus};
+
+/// Thuật toán rate limit. State của mỗi key là một `Vec<f64>` nhỏ (thời gian tính bằng giây
+/// Unix) để backend lưu được mà không cần biết thuật toán nào đang dùng.
+pub trait Algorithm: Send + Sync {
+    /// Tính thêm một request tại `now`. Vượt quota → Err(số giây cần chờ), state giữ nguyên.
+    fn acquire(&self, policy: &RateLimitPolicy, state: &mut Vec<f64>, now: f64) -> Result<RateLimitStatus, u64>;
+
+    /// Quota hiện tại (không tính thêm request)
+    fn status(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> RateLimitStatus;
+
+    /// State không còn ảnh hưởng (quota đã đầy lại) → key có thể bị dọn
+    fn is_idle(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> bool;
+}
+
+/// Tên thuật toán trong cấu hình policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgorithmKind {
+    SlidingLog,
+    SlidingWindow,
+    TokenBucket,
+    Gcra,
+}
+
+impl FromStr for AlgorithmKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sliding_log" => Ok(AlgorithmKind::SlidingLog),
+            "sliding_window" => Ok(AlgorithmKind::SlidingWindow),
+            "token_bucket" => Ok(AlgorithmKind::TokenBucket),
+            "gcra" => Ok(AlgorithmKind::Gcra),
+            other => Err(format!(
+                "unknown algorithm '{}' (expected sliding_log, sliding_window, token_bucket or gcra)",
+                other
+            )),
+        }
+    }
+}
+
+impl AlgorithmKind {
+    /// Tên trong cấu hình (cũng là tên dùng trong hàm SQL `rate_limit_acquire`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlgorithmKind::SlidingLog => "sliding_log",
+            AlgorithmKind::SlidingWindow => "sliding_window",
+            AlgorithmKind::TokenBucket => "token_bucket",
+            AlgorithmKind::Gcra => "gcra",
+        }
+    }
+
+    pub fn algorithm(self) -> &'static dyn Algorithm {
+        match self {
+            AlgorithmKind::SlidingLog => &SlidingLog,
+            AlgorithmKind::SlidingWindow => &SlidingWindow,
+            AlgorithmKind::TokenBucket => &TokenBucket,
+            AlgorithmKind::Gcra => &Gcra,
+        }
+    }
+}
+
+/// Làm tròn lên theo giây (số âm → 0)
+fn ceil_secs(secs: f64) -> u64 {
+    secs.max(0.0).ceil() as u64
+}
+
+/// Sliding log: lưu timestamp của từng request trong window (chính xác, O(limit) bộ nhớ mỗi key).
+/// State: các timestamp tăng dần.
+pub struct SlidingLog;
+
+impl Algorithm for SlidingLog {
+    fn acquire(&self, policy: &RateLimitPolicy, state: &mut Vec<f64>, now: f64) -> Result<RateLimitStatus, u64> {
+        let window = policy.window.as_secs_f64();
+        state.retain(|&t| t > now - window);
+
+        // Báo lại sau khi timestamp cũ nhất ra khỏi window
+        if state.len() >= policy.max_requests {
+            return Err(ceil_secs(state[0] + window - now).max(1));
+        }
+        state.push(now);
+        Ok(self.status(policy, state, now))
+    }
+
+    fn status(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> RateLimitStatus {
+        let window = policy.window.as_secs_f64();
+        let live: Vec<f64> = state.iter().copied().filter(|&t| t > now - window).collect();
+        RateLimitStatus {
+            limit: policy.max_requests,
+            remaining: policy.max_requests.saturating_sub(live.len()),
+            reset_secs: live.first().map_or(0, |oldest| ceil_secs(oldest + window - now)),
+        }
+    }
+
+    fn is_idle(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> bool {
+        state.last().is_none_or(|&t| t <= now - policy.window.as_secs_f64())
+    }
+}
+
+/// Sliding window counter: đếm theo window cố định, ước lượng window trượt bằng cách
+/// cộng số request của window trước theo tỉ lệ thời gian còn chồng lên.
+/// State: [bắt đầu window hiện tại, số request window hiện tại, số request window trước].
+pub struct SlidingWindow;
+
+impl SlidingWindow {
+    /// Dời state sang window chứa `now`, trả về (start, current, previous)
+    fn roll(window: f64, state: &[f64], now: f64) -> (f64, f64, f64) {
+        let start = (now / window).floor() * window;
+        match *state {
+            [s, current, previous] if s == start => (start, current, previous),
+            [s, current, _] if s == start - window => (start, 0.0, current),
+            _ => (start, 0.0, 0.0),
+        }
+    }
+
+    fn estimate(window: f64, start: f64, current: f64, previous: f64, now: f64) -> f64 {
+        previous * (1.0 - (now - start) / window) + current
+    }
+}
+
+impl Algorithm for SlidingWindow {
+    fn acquire(&self, policy: &RateLimitPolicy, state: &mut Vec<f64>, now: f64) -> Result<RateLimitStatus, u64> {
+        let window = policy.window.as_secs_f64();
+        let limit = policy.max_requests as f64;
+        let (start, current, previous) = Self::roll(window, state, now);
+
+        if Self::estimate(window, start, current, previous, now) + 1.0 > limit {
+            // Chờ tới khi phần của window trước giảm đủ, hoặc tới window sau nếu window này đã đầy
+            let wait = if current + 1.0 > limit || previous <= 0.0 {
+                start + window - now
+            } else {
+                start + window * (1.0 - (limit - 1.0 - current) / previous) - now
+            };
+            return Err(ceil_secs(wait).max(1));
+        }
+        *state = vec![start, current + 1.0, previous];
+        Ok(self.status(policy, state, now))
+    }
+
+    fn status(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> RateLimitStatus {
+        let window = policy.window.as_secs_f64();
+        let (start, current, previous) = Self::roll(window, state, now);
+        let used = Self::estimate(window, start, current, previous, now).ceil() as usize;
+        RateLimitStatus {
+            limit: policy.max_requests,
+            remaining: policy.max_requests.saturating_sub(used),
+            reset_secs: ceil_secs(start + window - now),
+        }
+    }
+
+    fn is_idle(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> bool {
+        // Hết window kế tiếp thì số đếm không còn được tính
+        state.first().is_none_or(|&start| now >= start + 2.0 * policy.window.as_secs_f64())
+    }
+}
+
+/// Token bucket: bucket chứa tối đa `max_requests` token, nạp lại đều trong `window`.
+/// State: [số token, thời điểm cập nhật].
+pub struct TokenBucket;
+
+impl TokenBucket {
+    /// (token hiện có, tốc độ nạp token/giây)
+    fn refill(policy: &RateLimitPolicy, state: &[f64], now: f64) -> (f64, f64) {
+        let capacity = policy.max_requests as f64;
+        let rate = capacity / policy.window.as_secs_f64();
+        let tokens = match *state {
+            [tokens, updated] => (tokens + (now - updated).max(0.0) * rate).min(capacity),
+            _ => capacity,
+        };
+        (tokens, rate)
+    }
+}
+
+impl Algorithm for TokenBucket {
+    fn acquire(&self, policy: &RateLimitPolicy, state: &mut Vec<f64>, now: f64) -> Result<RateLimitStatus, u64> {
+        let (tokens, rate) = Self::refill(policy, state, now);
+        if tokens < 1.0 {
+            return Err(ceil_secs((1.0 - tokens) / rate).max(1));
+        }
+        *state = vec![tokens - 1.0, now];
+        Ok(self.status(policy, state, now))
+    }
+
+    fn status(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> RateLimitStatus {
+        let (tokens, rate) = Self::refill(policy, state, now);
+        RateLimitStatus {
+            limit: policy.max_requests,
+            remaining: tokens.floor() as usize,
+            reset_secs: ceil_secs((policy.max_requests as f64 - tokens) / rate),
+        }
+    }
+
+    fn is_idle(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> bool {
+        Self::refill(policy, state, now).0 >= policy.max_requests as f64
+    }
+}
+
+/// GCRA (generic cell rate algorithm): chỉ lưu "theoretical arrival time" (TAT).
+/// Mỗi request đẩy TAT thêm `window / max_requests`, cho phép burst tối đa `max_requests`.
+/// State: [TAT].
+pub struct Gcra;
+
+impl Gcra {
+    fn tat(state: &[f64], now: f64) -> f64 {
+        state.first().map_or(now, |&tat| tat.max(now))
+    }
+}
+
+impl Algorithm for Gcra {
+    fn acquire(&self, policy: &RateLimitPolicy, state: &mut Vec<f64>, now: f64) -> Result<RateLimitStatus, u64> {
+        let window = policy.window.as_secs_f64();
+        let interval = window / policy.max_requests as f64;
+        let new_tat = Self::tat(state, now) + interval;
+
+        let allow_at = new_tat - window;
+        if now < allow_at {
+            return Err(ceil_secs(allow_at - now).max(1));
+        }
+        *state = vec![new_tat];
+        Ok(self.status(policy, state, now))
+    }
+
+    fn status(&self, policy: &RateLimitPolicy, state: &[f64], now: f64) -> RateLimitStatus {
+        let window = policy.window.as_secs_f64();
+        let interval = window / policy.max_requests as f64;
+        let tat = Self::tat(state, now);
+        RateLimitStatus {
+            limit: policy.max_requests,
+            remaining: ((window - (tat - now)) / interval).floor().max(0.0) as usize,
+            reset_secs: ceil_secs(tat - now),
+        }
+    }
+
+    fn is_idle(&self, _policy: &RateLimitPolicy, state: &[f64], now: f64) -> bool {
+        Self::tat(state, now) <= now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(spec: &str) -> RateLimitPolicy {
+        RateLimitPolicy::parse("test", spec).unwrap()
+    }
+
+    /// Gọi `acquire` `n` lần tại `now`, trả về số request được chấp nhận
+    fn acquire_n(policy: &RateLimitPolicy, state: &mut Vec<f64>, now: f64, n: usize) -> usize {
+        (0..n).filter(|_| policy.algorithm.algorithm().acquire(policy, state, now).is_ok()).count()
+    }
+
+    #[test]
+    fn sliding_log_allows_limit_then_waits_for_oldest() {
+        let p = policy("3/60:ip:sliding_log");
+        let mut state = Vec::new();
+        assert_eq!(acquire_n(&p, &mut state, 1000.0, 1), 1);
+        assert_eq!(acquire_n(&p, &mut state, 1010.0, 2), 2);
+        assert_eq!(SlidingLog.acquire(&p, &mut state, 1020.0).unwrap_err(), 40);
+        assert!(SlidingLog.acquire(&p, &mut state, 1060.5).is_ok());
+        assert!(!SlidingLog.is_idle(&p, &state, 1100.0));
+        assert!(SlidingLog.is_idle(&p, &state, 1120.5));
+    }
+
+    #[test]
+    fn sliding_window_counts_current_window() {
+        let p = policy("10/60:ip:sliding_window");
+        let mut state = Vec::new();
+        assert_eq!(acquire_n(&p, &mut state, 120.0, 10), 10);
+        let status = SlidingWindow.status(&p, &state, 130.0);
+        assert_eq!((status.remaining, status.reset_secs), (0, 50));
+        // Window hiện tại đã đầy → chờ tới window sau
+        assert_eq!(SlidingWindow.acquire(&p, &mut state, 130.0).unwrap_err(), 50);
+    }
+
+    #[test]
+    fn sliding_window_weights_previous_window() {
+        let p = policy("10/60:ip:sliding_window");
+        let mut state = Vec::new();
+        assert_eq!(acquire_n(&p, &mut state, 120.0, 10), 10);
+        // Đầu window sau, window trước vẫn tính đủ 10 → chờ tới khi phần chồng lên giảm còn 9
+        assert_eq!(SlidingWindow.acquire(&p, &mut state, 180.0).unwrap_err(), 6);
+        assert!(SlidingWindow.acquire(&p, &mut state, 187.0).is_ok());
+        assert_eq!(state, vec![180.0, 1.0, 10.0]);
+        // Cách hai window → số đếm cũ không còn tính
+        assert_eq!(acquire_n(&p, &mut state, 300.0, 11), 10);
+    }
+
+    #[test]
+    fn sliding_window_idle_after_next_window() {
+        let p = policy("10/60:ip:sliding_window");
+        let mut state = Vec::new();
+        assert!(SlidingWindow.is_idle(&p, &state, 0.0));
+        SlidingWindow.acquire(&p, &mut state, 120.0).unwrap();
+        assert!(!SlidingWindow.is_idle(&p, &state, 239.0));
+        assert!(SlidingWindow.is_idle(&p, &state, 240.0));
+    }
+
+    #[test]
+    fn token_bucket_burst_then_refill() {
+        let p = policy("6/60:ip:token_bucket");
+        let mut state = Vec::new();
+        assert_eq!(acquire_n(&p, &mut state, 1000.0, 6), 6);
+        // 1 token mỗi 10 giây
+        assert_eq!(TokenBucket.acquire(&p, &mut state, 1000.0).unwrap_err(), 10);
+        assert_eq!(TokenBucket.acquire(&p, &mut state, 1004.0).unwrap_err(), 6);
+        assert!(TokenBucket.acquire(&p, &mut state, 1010.0).is_ok());
+        let status = TokenBucket.status(&p, &state, 1010.0);
+        assert_eq!((status.remaining, status.reset_secs), (0, 60));
+    }
+
+    #[test]
+    fn token_bucket_idle_when_full() {
+        let p = policy("6/60:ip:token_bucket");
+        let mut state = Vec::new();
+        assert!(TokenBucket.is_idle(&p, &state, 0.0));
+        TokenBucket.acquire(&p, &mut state, 1000.0).unwrap();
+        assert!(!TokenBucket.is_idle(&p, &state, 1009.0));
+        assert!(TokenBucket.is_idle(&p, &state, 1010.0));
+        // Token không vượt quá dung lượng bucket
+        assert_eq!(TokenBucket.status(&p, &state, 5000.0).remaining, 6);
+    }
+
+    #[test]
+    fn gcra_burst_then_spacing() {
+        let p = policy("10/60:ip:gcra");
+        let mut state = Vec::new();
+        assert_eq!(acquire_n(&p, &mut state, 1000.0, 10), 10);
+        assert_eq!(state, vec![1060.0]);
+        // Mỗi request đẩy TAT thêm 6 giây
+        assert_eq!(Gcra.acquire(&p, &mut state, 1000.0).unwrap_err(), 6);
+        assert_eq!(Gcra.acquire(&p, &mut state, 1004.0).unwrap_err(), 2);
+        assert!(Gcra.acquire(&p, &mut state, 1006.0).is_ok());
+        assert_eq!(Gcra.acquire(&p, &mut state, 1006.0).unwrap_err(), 6);
+        let status = Gcra.status(&p, &state, 1006.0);
+        assert_eq!((status.remaining, status.reset_secs), (0, 60));
+    }
+
+    #[test]
+    fn gcra_idle_once_tat_passed() {
+        let p = policy("10/60:ip:gcra");
+        let mut state = Vec::new();
+        assert!(Gcra.is_idle(&p, &state, 0.0));
+        assert_eq!(acquire_n(&p, &mut state, 1000.0, 2), 2);
+        assert!(!Gcra.is_idle(&p, &state, 1011.0));
+        assert!(Gcra.is_idle(&p, &state, 1012.0));
+        assert_eq!(Gcra.status(&p, &state, 1006.0).remaining, 9);
+    }
+
+    #[test]
+    fn algorithm_names_round_trip() {
+        for kind in [AlgorithmKind::SlidingLog, AlgorithmKind::SlidingWindow, AlgorithmKind::TokenBucket, AlgorithmKind::Gcra] {
+            assert_eq!(kind.as_str().parse::<AlgorithmKind>(), Ok(kind));
+        }
+        assert!("leaky_bucket".parse::<AlgorithmKind>().is_err());
+    }
+}
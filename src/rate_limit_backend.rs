@@ -0,0 +1,154 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use lru::LruCache;
+use sqlx::PgPool;
+
+use crate::config::CONFIG;
+use crate::db;
+use crate::errors::ApiError;
+use crate::rate_limit::{self, RateLimitPolicy, RateLimitStatus};
+
+/// Nơi lưu state rate limit. `key` đã gồm tên policy: "<policy>|<identity>".
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    /// Tính thêm một request tại `now`; Ok(Err(số giây cần chờ)) khi vượt quota
+    async fn acquire(&self, policy: &RateLimitPolicy, key: &str, now: f64) -> Result<Result<RateLimitStatus, u64>, ApiError>;
+
+    /// Dọn các key không còn ảnh hưởng tới quota, trả về số key đã xóa
+    async fn sweep(&self, now: f64) -> Result<u64, ApiError>;
+}
+
+/// Số shard của backend bộ nhớ: mỗi shard có lock và LRU riêng
+const MEMORY_SHARDS: usize = 16;
+
+/// Policy của một key "<policy>|<identity>"
+fn key_policy(key: &str) -> Option<&'static RateLimitPolicy> {
+    rate_limit::find_policy(key.split_once('|').map_or(key, |(name, _)| name))
+}
+
+/// Backend trong bộ nhớ — mỗi instance đếm riêng. Tổng số key bị giới hạn bởi RATE_LIMIT_MAX_KEYS:
+/// khi một shard đầy, key ít được dùng gần đây nhất bị bỏ (client đó bắt đầu lại với quota đầy).
+pub struct MemoryRateLimitBackend {
+    /// Key -> state của thuật toán (xem `rate_limit_algorithms`), chia shard theo hash của key
+    shards: Vec<Mutex<LruCache<String, Vec<f64>>>>,
+    hasher: RandomState,
+    /// Đã cảnh báo phải bỏ key còn hoạt động chưa (chỉ log một lần cho tới lần dọn tiếp theo)
+    evict_warned: AtomicBool,
+}
+
+impl Default for MemoryRateLimitBackend {
+    fn default() -> Self {
+        let per_shard = NonZeroUsize::new(CONFIG.rate_limit_max_keys.div_ceil(MEMORY_SHARDS)).unwrap_or(NonZeroUsize::MIN);
+        MemoryRateLimitBackend {
+            shards: (0..MEMORY_SHARDS).map(|_| Mutex::new(LruCache::new(per_shard))).collect(),
+            hasher: RandomState::new(),
+            evict_warned: AtomicBool::new(false),
+        }
+    }
+}
+
+impl MemoryRateLimitBackend {
+    fn shard(&self, key: &str) -> MutexGuard<'_, LruCache<String, Vec<f64>>> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        self.shards[index].lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for MemoryRateLimitBackend {
+    async fn acquire(&self, policy: &RateLimitPolicy, key: &str, now: f64) -> Result<Result<RateLimitStatus, u64>, ApiError> {
+        let mut shard = self.shard(key);
+        if let Some(state) = shard.get_mut(key) {
+            return Ok(policy.algorithm.algorithm().acquire(policy, state, now));
+        }
+
+        let mut state = Vec::new();
+        let result = policy.algorithm.algorithm().acquire(policy, &mut state, now);
+        // Shard đầy → LRU bỏ key cũ nhất; thường là key đã hết tác dụng, nếu không thì cảnh báo
+        if let Some((evicted, evicted_state)) = shard.push(key.to_string(), state)
+            && key_policy(&evicted).is_some_and(|p| !p.algorithm.algorithm().is_idle(p, &evicted_state, now))
+            && !self.evict_warned.swap(true, Ordering::Relaxed)
+        {
+            tracing::warn!(
+                "Rate limiter reached RATE_LIMIT_MAX_KEYS ({}), evicting least recently used clients",
+                CONFIG.rate_limit_max_keys
+            );
+        }
+        Ok(result)
+    }
+
+    async fn sweep(&self, now: f64) -> Result<u64, ApiError> {
+        let mut removed = 0;
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap_or_else(|e| e.into_inner());
+            let idle: Vec<String> = shard
+                .iter()
+                .filter(|(key, state)| {
+                    key_policy(key).is_none_or(|policy| policy.algorithm.algorithm().is_idle(policy, state, now))
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in idle {
+                shard.pop(&key);
+                removed += 1;
+            }
+        }
+        self.evict_warned.store(false, Ordering::Relaxed);
+        Ok(removed)
+    }
+}
+
+/// Backend Postgres (bảng UNLOGGED rate_limit_state) — các instance dùng chung quota
+pub struct PgRateLimitBackend {
+    pool: PgPool,
+    /// Key đang bị chặn -> thời điểm hết chặn: từ chối luôn, không hỏi DB
+    blocked: DashMap<String, f64>,
+}
+
+impl PgRateLimitBackend {
+    pub fn new(pool: PgPool) -> Self {
+        PgRateLimitBackend { pool, blocked: DashMap::new() }
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for PgRateLimitBackend {
+    async fn acquire(&self, policy: &RateLimitPolicy, key: &str, now: f64) -> Result<Result<RateLimitStatus, u64>, ApiError> {
+        if let Some(until) = self.blocked.get(key).map(|until| *until)
+            && until > now
+        {
+            return Ok(Err((until - now).ceil() as u64));
+        }
+
+        // Thuật toán chạy trong SQL theo đồng hồ của DB; `now` của instance chỉ dùng cho cache cục bộ
+        let acquired = db::acquire_rate_limit(
+            &self.pool,
+            key,
+            policy.algorithm.as_str(),
+            policy.max_requests as i32,
+            policy.window.as_secs_f64(),
+        )
+        .await?;
+
+        let algorithm = policy.algorithm.algorithm();
+        if acquired.allowed {
+            return Ok(Ok(algorithm.status(policy, &acquired.state, acquired.now)));
+        }
+        // State không đổi khi bị từ chối → chạy lại phía Rust để tính Retry-After
+        let mut state = acquired.state;
+        let retry_after = algorithm.acquire(policy, &mut state, acquired.now).err().unwrap_or(1);
+        self.blocked.insert(key.to_string(), now + retry_after as f64);
+        Ok(Err(retry_after))
+    }
+
+    async fn sweep(&self, now: f64) -> Result<u64, ApiError> {
+        self.blocked.retain(|_, until| *until > now);
+        db::purge_rate_limit_state(&self.pool).await
+    }
+}
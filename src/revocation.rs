@@ -0,0 +1,77 @@
+use chrono::{DateTime, TimeZone, Utc};
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use sqlx::PgPool;
+
+use crate::db;
+use crate::errors::ApiError;
+use crate::jwt::Claims;
+
+/// Thời gian cache kết quả "chưa bị thu hồi" trước khi hỏi lại DB (giây)
+const CACHE_TTL_SECS: i64 = 30;
+
+lazy_static! {
+    /// jti đã bị thu hồi -> exp của token (giữ tới khi token tự hết hạn)
+    static ref REVOKED: DashMap<String, i64> = DashMap::new();
+    /// jti đã kiểm tra là hợp lệ -> (user_id, thời điểm kiểm tra)
+    static ref CHECKED: DashMap<String, (i32, i64)> = DashMap::new();
+}
+
+/// Kiểm tra token đã bị thu hồi chưa (cache trong bộ nhớ, fallback Postgres)
+pub async fn is_revoked(pool: &PgPool, claims: &Claims) -> Result<bool, ApiError> {
+    let now = Utc::now().timestamp();
+
+    if REVOKED.contains_key(&claims.jti) {
+        return Ok(true);
+    }
+    if let Some(entry) = CHECKED.get(&claims.jti)
+        && now - entry.1 < CACHE_TTL_SECS
+    {
+        return Ok(false);
+    }
+
+    let (revoked, revoked_before) = db::get_token_revocation(pool, &claims.jti, claims.sub).await?;
+    // `iat` chỉ tính theo giây còn mốc thu hồi có phần lẻ: token cấp trong cùng giây với mốc
+    // cũng bị coi là đã thu hồi (an toàn hơn; user chỉ cần đăng nhập lại)
+    let revoked = revoked
+        || revoked_before.is_some_and(|cutoff| (claims.iat as i64) <= cutoff.timestamp());
+
+    if revoked {
+        CHECKED.remove(&claims.jti);
+        REVOKED.insert(claims.jti.clone(), claims.exp as i64);
+    } else {
+        CHECKED.insert(claims.jti.clone(), (claims.sub, now));
+    }
+    Ok(revoked)
+}
+
+/// Thu hồi một access token (logout)
+pub async fn revoke_token(pool: &PgPool, claims: &Claims) -> Result<(), ApiError> {
+    let expires_at: DateTime<Utc> = Utc
+        .timestamp_opt(claims.exp as i64, 0)
+        .single()
+        .unwrap_or_else(Utc::now);
+
+    db::insert_revoked_token(pool, &claims.jti, claims.sub, expires_at).await?;
+
+    CHECKED.remove(&claims.jti);
+    REVOKED.insert(claims.jti.clone(), claims.exp as i64);
+    purge_expired();
+    Ok(())
+}
+
+/// Thu hồi mọi token của user (logout khỏi tất cả thiết bị)
+pub async fn revoke_all_for_user(pool: &PgPool, user_id: i32) -> Result<(), ApiError> {
+    db::revoke_all_user_tokens(pool, user_id).await?;
+
+    // Bỏ cache "hợp lệ" của user để lần kiểm tra sau hỏi lại DB
+    CHECKED.retain(|_, (uid, _)| *uid != user_id);
+    Ok(())
+}
+
+/// Dọn các entry đã hết hạn khỏi cache (gọi khi thu hồi token và định kỳ từ main)
+pub fn purge_expired() {
+    let now = Utc::now().timestamp();
+    REVOKED.retain(|_, exp| *exp > now);
+    CHECKED.retain(|_, (_, checked_at)| now - *checked_at < CACHE_TTL_SECS);
+}
@@ -1,102 +1,498 @@
-use warp::Filter;
+use warp::{Filter, Reply};
 use sqlx::PgPool;
+use std::net::IpAddr;
 use crate::handlers;
-use crate::models::{RegisterRequest, LoginRequest};
+use crate::models::{RegisterRequest, LoginRequest, RefreshRequest, RoleRequest, ScopedTokenRequest, CreateApiKeyRequest, UserListQuery, UpdateUserRequest, ChangePasswordRequest, ForgotPasswordRequest, ResetPasswordRequest, VerifyEmailRequest, TotpCodeRequest, MfaLoginRequest, AuditQuery, Role};
+use crate::api_keys;
+use crate::client_ip::client_ip;
 use crate::jwt;
+use crate::revocation;
+use crate::scopes;
 use crate::errors::ApiError;
-use crate::rate_limit::{RateLimiter, with_rate_limit};
+use crate::rate_limit::{self, RateLimitStatus, RateLimiter};
 
-/// Filter xác thực JWT
-pub fn with_auth() -> impl Filter<Extract = (jwt::Claims,), Error = warp::Rejection> + Clone {
+/// Filter xác thực: `Bearer <JWT>` (từ chối token đã bị thu hồi) hoặc `ApiKey <key>`
+pub fn with_auth(pool: PgPool) -> impl Filter<Extract = (jwt::Claims,), Error = warp::Rejection> + Clone {
     warp::header::<String>("authorization")
-        .and_then(|auth_header: String| async move {
-            if !auth_header.starts_with("Bearer ") {
-                return Err(warp::reject::custom(ApiError::Unauthorized("Missing Bearer token".into())));
-            }
-            let token = auth_header.trim_start_matches("Bearer ").trim();
-            match jwt::verify_token(token).await {
-                Ok(claims) => Ok(claims),
-                Err(msg) => Err(warp::reject::custom(ApiError::Unauthorized(msg))),
+        .and_then(move |auth_header: String| {
+            let pool = pool.clone();
+            async move {
+                // API key: không có session/idle timeout, thu hồi qua revoked_at
+                if let Some(key) = auth_header.strip_prefix("ApiKey ") {
+                    return api_keys::authenticate(&pool, key.trim())
+                        .await
+                        .map_err(warp::reject::custom);
+                }
+                if !auth_header.starts_with("Bearer ") {
+                    return Err(warp::reject::custom(ApiError::Unauthorized("Missing Bearer token".into())));
+                }
+                let token = auth_header.trim_start_matches("Bearer ").trim();
+                let claims = jwt::verify_token(token)
+                    .await
+                    .map_err(|msg| warp::reject::custom(ApiError::Unauthorized(msg)))?;
+
+                if revocation::is_revoked(&pool, &claims).await.map_err(warp::reject::custom)? {
+                    return Err(warp::reject::custom(ApiError::Unauthorized("Token revoked".into())));
+                }
+                Ok(claims)
             }
         })
 }
 
+/// Filter yêu cầu role tối thiểu (admin > moderator > user)
+pub fn with_role(pool: PgPool, min_role: Role) -> impl Filter<Extract = (jwt::Claims,), Error = warp::Rejection> + Clone {
+    with_auth(pool).and_then(move |claims: jwt::Claims| async move {
+        if claims.role < min_role {
+            return Err(warp::reject::custom(ApiError::Forbidden(format!("Requires {} role", min_role))));
+        }
+        Ok(claims)
+    })
+}
+
+/// Filter yêu cầu token có scope (bọc quanh with_auth / with_role)
+pub fn with_scope<F>(auth: F, scope: &'static str) -> impl Filter<Extract = (jwt::Claims,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (jwt::Claims,), Error = warp::Rejection> + Clone,
+{
+    auth.and_then(move |claims: jwt::Claims| async move {
+        if !claims.has_scope(scope) {
+            return Err(warp::reject::custom(ApiError::Forbidden(format!("Missing scope {}", scope))));
+        }
+        Ok(claims)
+    })
+}
+
 /// Tạo tất cả routes
-pub fn create_routes(pool: PgPool) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    let db_filter = warp::any().map(move || pool.clone());
+pub fn create_routes(pool: PgPool, limiter: RateLimiter) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let auth = with_auth(pool.clone());
+    let admin = with_role(pool.clone(), Role::Admin);
 
-    // Khởi tạo RateLimiter
-    let limiter = RateLimiter::new();
-    let rate_limit_filter = with_rate_limit(limiter);
+    // Mỗi route gắn một policy rate limit (RATE_LIMIT_POLICIES): `check()` đếm request ngay sau path + method,
+    // `rate_limit::respond` gắn quota của đúng lần đếm đó vào header RateLimit-* của response.
+    // Route được `.boxed()` để future nằm trên heap: chuỗi `.or()` dài làm tràn stack ở bản debug.
+    let auth_limit = limiter.policy("auth");
+    let avatar_limit = limiter.policy("avatar");
+    let default_limit = limiter.policy("default");
+
+    let db_filter = warp::any().map(move || pool.clone());
 
     // Root
     let root = warp::path::end()
         .and(warp::get())
-        .and(rate_limit_filter.clone())
-        .and_then(handlers::root_handler);
+        .and(default_limit.check())
+        .and_then(|limit: RateLimitStatus| rate_limit::respond(limit, handlers::root_handler()))
+        .boxed();
+
+    // JWKS (không rate limit: các service khác poll định kỳ)
+    let jwks = warp::path!(".well-known" / "jwks.json")
+        .and(warp::get())
+        .and_then(handlers::jwks_handler);
 
     // Register
     let register = warp::path("register")
         .and(warp::post())
-        .and(rate_limit_filter.clone())
+        .and(auth_limit.check())
         .and(warp::body::json::<RegisterRequest>())
         .and(db_filter.clone())
-        .and_then(handlers::register_handler);
+        .and(client_ip())
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(|limit: RateLimitStatus, body: RegisterRequest, pool: PgPool, ip: Option<IpAddr>, ua: Option<String>| {
+            rate_limit::respond(limit, handlers::register_handler(body, pool, ip, ua))
+        })
+        .boxed();
 
     // Login
-    let login = warp::path("login")
+    let login = warp::path!("login")
         .and(warp::post())
-        .and(rate_limit_filter.clone())
+        .and(auth_limit.check())
         .and(warp::body::json::<LoginRequest>())
         .and(db_filter.clone())
-        .and_then(handlers::login_handler);
+        .and(client_ip())
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(|limit: RateLimitStatus, body: LoginRequest, pool: PgPool, ip: Option<IpAddr>, ua: Option<String>| {
+            rate_limit::respond(limit, handlers::login_handler(body, pool, ip, ua))
+        })
+        .boxed();
+
+    // Login bước 2: mã TOTP / mã khôi phục
+    let login_mfa = warp::path!("login" / "mfa")
+        .and(warp::post())
+        .and(auth_limit.check())
+        .and(warp::body::json::<MfaLoginRequest>())
+        .and(db_filter.clone())
+        .and(client_ip())
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(|limit: RateLimitStatus, body: MfaLoginRequest, pool: PgPool, ip: Option<IpAddr>, ua: Option<String>| {
+            rate_limit::respond(limit, handlers::login_mfa_handler(body, pool, ip, ua))
+        })
+        .boxed();
+
+    // Refresh token
+    let refresh = warp::path!("token" / "refresh")
+        .and(warp::post())
+        .and(auth_limit.check())
+        .and(warp::body::json::<RefreshRequest>())
+        .and(db_filter.clone())
+        .and_then(|limit: RateLimitStatus, body: RefreshRequest, pool: PgPool| {
+            rate_limit::respond(limit, handlers::refresh_token_handler(body, pool))
+        })
+        .boxed();
+
+    // Cấp token giới hạn scope
+    let scoped_token = warp::path!("token" / "scoped")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(warp::body::json::<ScopedTokenRequest>())
+        .and(db_filter.clone())
+        .and(auth.clone())
+        .and(client_ip())
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(|limit: RateLimitStatus, body: ScopedTokenRequest, pool: PgPool, claims: jwt::Claims, ip: Option<IpAddr>, ua: Option<String>| {
+            rate_limit::respond(limit, handlers::scoped_token_handler(body, pool, claims, ip, ua))
+        })
+        .boxed();
+
+    // Logout (phiên hiện tại)
+    let logout = warp::path!("logout")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(auth.clone())
+        .and_then(|limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::logout_handler(pool, claims))
+        })
+        .boxed();
+
+    // Logout khỏi tất cả thiết bị
+    let logout_all = warp::path!("logout" / "all")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::SESSIONS_WRITE))
+        .and_then(|limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::logout_all_handler(pool, claims))
+        })
+        .boxed();
+
+    // Danh sách session của user
+    let list_sessions = warp::path!("users" / i32 / "sessions")
+        .and(warp::get())
+        .and(default_limit.check())
+        .and(with_scope(auth.clone(), scopes::SESSIONS_READ))
+        .and_then(|id: i32, limit: RateLimitStatus, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::list_sessions_handler(id, claims))
+        })
+        .boxed();
+
+    // Hủy một session
+    let revoke_session = warp::path!("users" / i32 / "sessions" / String)
+        .and(warp::delete())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::SESSIONS_WRITE))
+        .and_then(|id: i32, sid: String, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::revoke_session_handler(id, sid, pool, claims))
+        })
+        .boxed();
+
+    // Tạo API key
+    let create_api_key = warp::path!("users" / i32 / "api-keys")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(warp::body::json::<CreateApiKeyRequest>())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::API_KEYS_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, body: CreateApiKeyRequest, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::create_api_key_handler(id, body, pool, claims))
+        })
+        .boxed();
+
+    // Danh sách API key
+    let list_api_keys = warp::path!("users" / i32 / "api-keys")
+        .and(warp::get())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::API_KEYS_READ))
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::list_api_keys_handler(id, pool, claims))
+        })
+        .boxed();
+
+    // Thu hồi API key
+    let revoke_api_key = warp::path!("users" / i32 / "api-keys" / i32)
+        .and(warp::delete())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::API_KEYS_WRITE))
+        .and_then(|id: i32, key_id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::revoke_api_key_handler(id, key_id, pool, claims))
+        })
+        .boxed();
+
+    // Danh sách user (admin)
+    let list_users = warp::path!("users")
+        .and(warp::get())
+        .and(default_limit.check())
+        .and(warp::query::<UserListQuery>())
+        .and(db_filter.clone())
+        .and(with_scope(admin.clone(), scopes::USERS_READ))
+        .and_then(|limit: RateLimitStatus, query: UserListQuery, pool: PgPool, _claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::list_users_handler(query, pool))
+        })
+        .boxed();
+
+    // Xem hồ sơ user
+    let get_user = warp::path!("users" / i32)
+        .and(warp::get())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(auth.clone())
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::get_user_handler(id, pool, claims))
+        })
+        .boxed();
+
+    // Sửa hồ sơ user
+    let update_user = warp::path!("users" / i32)
+        .and(warp::patch())
+        .and(default_limit.check())
+        .and(warp::body::json::<UpdateUserRequest>())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::PROFILE_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, body: UpdateUserRequest, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, async move {
+                // Admin được sửa hồ sơ bất kỳ user nào
+                if claims.sub != id && claims.role != Role::Admin {
+                    return Err(warp::reject::custom(ApiError::NotAllowed));
+                }
+                handlers::update_user_handler(id, body, pool).await
+            })
+        })
+        .boxed();
+
+    // Đổi mật khẩu
+    let change_password = warp::path!("users" / i32 / "password")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(warp::body::json::<ChangePasswordRequest>())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::PROFILE_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, body: ChangePasswordRequest, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::change_password_handler(id, body, pool, claims))
+        })
+        .boxed();
+
+    // Quên mật khẩu (gửi link qua email)
+    let forgot_password = warp::path!("password" / "forgot")
+        .and(warp::post())
+        .and(auth_limit.check())
+        .and(warp::body::json::<ForgotPasswordRequest>())
+        .and(db_filter.clone())
+        .and_then(|limit: RateLimitStatus, body: ForgotPasswordRequest, pool: PgPool| {
+            rate_limit::respond(limit, handlers::forgot_password_handler(body, pool))
+        })
+        .boxed();
+
+    // Đặt lại mật khẩu bằng token
+    let reset_password = warp::path!("password" / "reset")
+        .and(warp::post())
+        .and(auth_limit.check())
+        .and(warp::body::json::<ResetPasswordRequest>())
+        .and(db_filter.clone())
+        .and_then(|limit: RateLimitStatus, body: ResetPasswordRequest, pool: PgPool| {
+            rate_limit::respond(limit, handlers::reset_password_handler(body, pool))
+        })
+        .boxed();
+
+    // Gửi mã xác thực email
+    let request_email_verification = warp::path!("users" / i32 / "email" / "verify")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::PROFILE_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::request_email_verification_handler(id, pool, claims))
+        })
+        .boxed();
+
+    // Xác nhận email bằng mã
+    let verify_email = warp::path!("verify-email")
+        .and(warp::post())
+        .and(auth_limit.check())
+        .and(warp::body::json::<VerifyEmailRequest>())
+        .and(db_filter.clone())
+        .and_then(|limit: RateLimitStatus, body: VerifyEmailRequest, pool: PgPool| {
+            rate_limit::respond(limit, handlers::verify_email_handler(body, pool))
+        })
+        .boxed();
+
+    // Tạo secret 2FA (TOTP)
+    let totp_setup = warp::path!("users" / i32 / "2fa" / "setup")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::PROFILE_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::setup_totp_handler(id, pool, claims))
+        })
+        .boxed();
+
+    // Bật 2FA
+    let totp_enable = warp::path!("users" / i32 / "2fa" / "enable")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(warp::body::json::<TotpCodeRequest>())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::PROFILE_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, body: TotpCodeRequest, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::enable_totp_handler(id, body, pool, claims))
+        })
+        .boxed();
 
     // Delete user
     let delete = warp::path!("users" / i32)
         .and(warp::delete())
-        .and(rate_limit_filter.clone())
+        .and(default_limit.check())
         .and(db_filter.clone())
-        .and(with_auth())
-        .and_then(|id: i32, pool: PgPool, claims: jwt::Claims| async move {
-            if claims.sub != id {
-                return Err(warp::reject::custom(ApiError::NotAllowed));
-            }
-            handlers::delete_user_handler(id, pool).await
-        });
+        .and(with_scope(auth.clone(), scopes::USER_DELETE))
+        .and(client_ip())
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims, ip: Option<IpAddr>, ua: Option<String>| {
+            rate_limit::respond(limit, async move {
+                // Admin được xóa bất kỳ user nào
+                if claims.sub != id && claims.role != Role::Admin {
+                    return Err(warp::reject::custom(ApiError::NotAllowed));
+                }
+                handlers::delete_user_handler(id, pool, claims, ip, ua).await
+            })
+        })
+        .boxed();
+
+    // Đổi role (admin)
+    let set_role = warp::path!("users" / i32 / "role")
+        .and(warp::put())
+        .and(default_limit.check())
+        .and(warp::body::json::<RoleRequest>())
+        .and(db_filter.clone())
+        .and(with_scope(admin.clone(), scopes::USERS_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, body: RoleRequest, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::set_role_handler(id, body, pool, claims))
+        })
+        .boxed();
+
+    // Khôi phục user đã xóa (admin)
+    let restore_user = warp::path!("users" / i32 / "restore")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(admin.clone(), scopes::USERS_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, _claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::restore_user_handler(id, pool))
+        })
+        .boxed();
+
+    // Mở khóa tài khoản (admin)
+    let unlock_user = warp::path!("users" / i32 / "unlock")
+        .and(warp::post())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(admin.clone(), scopes::USERS_WRITE))
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, _claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::unlock_user_handler(id, pool))
+        })
+        .boxed();
 
     // Upload avatar
     let upload_avatar = warp::path!("users" / i32 / "avatar")
         .and(warp::post())
-        .and(rate_limit_filter.clone())
+        .and(default_limit.check())
         .and(db_filter.clone())
-        .and(with_auth())
+        .and(with_scope(auth.clone(), scopes::AVATAR_WRITE))
+        .and(client_ip())
+        .and(warp::header::optional::<String>("user-agent"))
         .and(warp::multipart::form().max_length(5_000_000)) // giới hạn 5MB
-        .and_then(|id: i32, pool: PgPool, claims: jwt::Claims, form: warp::multipart::FormData| async move {
-            handlers::upload_avatar_handler(id, pool, claims, form).await
-        });
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims, ip: Option<IpAddr>, ua: Option<String>, form: warp::multipart::FormData| {
+            rate_limit::respond(limit, handlers::upload_avatar_handler(id, pool, claims, ip, ua, form))
+        })
+        .boxed();
+
+    // Xuất dữ liệu cá nhân (ZIP)
+    let export_user = warp::path!("users" / i32 / "export")
+        .and(warp::get())
+        .and(default_limit.check())
+        .and(db_filter.clone())
+        .and(with_scope(auth.clone(), scopes::DATA_EXPORT))
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool, claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::export_user_handler(id, pool, claims))
+        })
+        .boxed();
+
+    // Nhật ký audit (admin)
+    let audit_log = warp::path!("admin" / "audit")
+        .and(warp::get())
+        .and(default_limit.check())
+        .and(warp::query::<AuditQuery>())
+        .and(db_filter.clone())
+        .and(with_scope(admin.clone(), scopes::AUDIT_READ))
+        .and_then(|limit: RateLimitStatus, query: AuditQuery, pool: PgPool, _claims: jwt::Claims| {
+            rate_limit::respond(limit, handlers::list_audit_events_handler(query, pool))
+        })
+        .boxed();
 
     // Get avatar
     let get_avatar = warp::path!("users" / i32 / "avatar")
         .and(warp::get())
-        .and(rate_limit_filter.clone())
+        .and(avatar_limit.check())
         .and(db_filter.clone())
-        .and_then(|id: i32, pool: PgPool| async move {
-            handlers::get_avatar_handler(id, pool).await
-        });
+        .and_then(|id: i32, limit: RateLimitStatus, pool: PgPool| {
+            rate_limit::respond(limit, handlers::get_avatar_handler(id, pool))
+        })
+        .boxed();
 
     // Kết hợp tất cả route
-    root.or(register)
+    root.or(jwks)
+        .or(register)
         .or(login)
+        .or(login_mfa)
+        .or(refresh)
+        .or(scoped_token)
+        .or(logout)
+        .or(logout_all)
+        .or(list_users)
+        .or(get_user)
+        .or(update_user)
+        .or(change_password)
+        .or(forgot_password)
+        .or(reset_password)
+        .or(request_email_verification)
+        .or(verify_email)
+        .or(totp_setup)
+        .or(totp_enable)
         .or(delete)
+        .or(set_role)
+        .or(unlock_user)
+        .or(restore_user)
         .or(upload_avatar)
         .or(get_avatar)
+        .or(export_user)
+        .or(audit_log)
+        .or(list_sessions)
+        .or(revoke_session)
+        .or(create_api_key)
+        .or(list_api_keys)
+        .or(revoke_api_key)
         .recover(|err: warp::Rejection| async move {
             if let Some(e) = err.find::<ApiError>() {
                 let code = e.status_code();
-                let msg = serde_json::json!({ "error": e.to_string() });
-                return Ok(warp::reply::with_status(warp::reply::json(&msg), code));
+                let mut msg = serde_json::json!({ "error": e.to_string() });
+                if let Some(details) = e.details() {
+                    msg["details"] = serde_json::json!(details);
+                }
+                let mut resp = warp::reply::with_status(warp::reply::json(&msg), code).into_response();
+                rate_limit::add_rejection_headers(&mut resp, e);
+                return Ok(resp);
             }
             let msg = serde_json::json!({ "error": "internal server error" });
-            Ok(warp::reply::with_status(warp::reply::json(&msg), warp::http::StatusCode::INTERNAL_SERVER_ERROR))
+            Ok(warp::reply::with_status(warp::reply::json(&msg), warp::http::StatusCode::INTERNAL_SERVER_ERROR).into_response())
         })
 }
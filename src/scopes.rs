@@ -0,0 +1,56 @@
+use crate::models::Role;
+
+// Các scope có thể cấp cho token
+pub const AVATAR_WRITE: &str = "avatar:write";
+pub const USER_DELETE: &str = "user:delete";
+pub const PROFILE_WRITE: &str = "profile:write";
+pub const DATA_EXPORT: &str = "data:export";
+pub const SESSIONS_READ: &str = "sessions:read";
+pub const SESSIONS_WRITE: &str = "sessions:write";
+pub const USERS_READ: &str = "users:read";
+pub const USERS_WRITE: &str = "users:write";
+pub const AUDIT_READ: &str = "audit:read";
+pub const API_KEYS_READ: &str = "api-keys:read";
+pub const API_KEYS_WRITE: &str = "api-keys:write";
+
+/// Scope của mọi user
+const USER_SCOPES: &[&str] = &[
+    AVATAR_WRITE, USER_DELETE, PROFILE_WRITE, DATA_EXPORT, SESSIONS_READ, SESSIONS_WRITE, API_KEYS_READ,
+    API_KEYS_WRITE,
+];
+
+/// Scope không được cấp mặc định cho API key và không được cấp khi tạo key bằng API key,
+/// để key bị lộ không tự tạo thêm key hay thu hồi phiên đăng nhập của user
+pub const API_KEY_RESTRICTED: &[&str] = &[API_KEYS_READ, API_KEYS_WRITE, SESSIONS_WRITE];
+
+/// Scope đầy đủ theo role (dùng cho token khi login)
+pub fn default_scopes(role: Role) -> Vec<&'static str> {
+    let mut scopes = USER_SCOPES.to_vec();
+    if role >= Role::Moderator {
+        scopes.push(USERS_READ);
+    }
+    if role >= Role::Admin {
+        scopes.push(USERS_WRITE);
+        scopes.push(AUDIT_READ);
+    }
+    scopes
+}
+
+/// Scope hợp lệ của role, giới hạn trong `requested` (nếu có).
+/// Trả về chuỗi scope phân cách bằng dấu cách như claim `scope` của OAuth.
+pub fn grant(role: Role, requested: Option<&str>) -> String {
+    let allowed = default_scopes(role);
+    match requested {
+        Some(req) => req
+            .split_whitespace()
+            .filter(|s| allowed.contains(s))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => allowed.join(" "),
+    }
+}
+
+/// Kiểm tra chuỗi scope có chứa `scope` không
+pub fn contains(scope_claim: &str, scope: &str) -> bool {
+    scope_claim.split_whitespace().any(|s| s == scope)
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::PgPool;
+use tokio::sync::Mutex;
+
+use crate::config::CONFIG;
+use crate::db;
+use crate::errors::ApiError;
+use crate::models::Session;
+
+/// Trạng thái session khi kiểm tra idle timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// Session còn hoạt động, last_activity đã được cập nhật
+    Active,
+    /// Session quá thời gian không hoạt động (vẫn có thể resume bằng refresh token)
+    Idle,
+    /// Session không tồn tại hoặc đã bị thu hồi
+    Missing,
+}
+
+/// Nơi lưu trạng thái hoạt động của từng phiên đăng nhập (theo session id)
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Tạo session mới khi login (kèm IP và user agent của client)
+    async fn create(&self, id: &str, user_id: i32, ip: Option<&str>, user_agent: Option<&str>) -> Result<(), ApiError>;
+
+    /// Lấy session theo ID
+    async fn get(&self, id: &str) -> Result<Option<Session>, ApiError>;
+
+    /// Danh sách session của user
+    async fn list(&self, user_id: i32) -> Result<Vec<Session>, ApiError>;
+
+    /// Kiểm tra idle timeout và cập nhật last_activity nếu session còn hoạt động
+    async fn touch(&self, id: &str, timeout_secs: i64) -> Result<SessionStatus, ApiError>;
+
+    /// Kích hoạt lại session (khi refresh token), trả về false nếu session không tồn tại
+    async fn resume(&self, id: &str) -> Result<bool, ApiError>;
+
+    /// Xóa một session
+    async fn remove(&self, id: &str) -> Result<(), ApiError>;
+
+    /// Xóa mọi session của user
+    async fn remove_user(&self, user_id: i32) -> Result<(), ApiError>;
+
+    /// Dọn các session không hoạt động lâu hơn `max_idle_secs`
+    async fn purge_stale(&self, max_idle_secs: i64) -> Result<u64, ApiError>;
+}
+
+/// Backend trong bộ nhớ — chỉ phù hợp khi chạy một instance
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn create(&self, id: &str, user_id: i32, ip: Option<&str>, user_agent: Option<&str>) -> Result<(), ApiError> {
+        let mut map = self.sessions.lock().await;
+        let now = Utc::now();
+        map.insert(id.to_string(), Session {
+            id: id.to_string(),
+            user_id,
+            created_at: now,
+            last_activity: now,
+            ip: ip.map(str::to_string),
+            user_agent: user_agent.map(str::to_string),
+        });
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Session>, ApiError> {
+        Ok(self.sessions.lock().await.get(id).cloned())
+    }
+
+    async fn list(&self, user_id: i32) -> Result<Vec<Session>, ApiError> {
+        let map = self.sessions.lock().await;
+        let mut sessions: Vec<Session> = map.values().filter(|s| s.user_id == user_id).cloned().collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_activity));
+        Ok(sessions)
+    }
+
+    async fn touch(&self, id: &str, timeout_secs: i64) -> Result<SessionStatus, ApiError> {
+        let mut map = self.sessions.lock().await;
+        let now = Utc::now();
+        match map.get_mut(id) {
+            None => Ok(SessionStatus::Missing),
+            Some(s) if (now - s.last_activity).num_seconds() > timeout_secs => Ok(SessionStatus::Idle),
+            Some(s) => {
+                s.last_activity = now;
+                Ok(SessionStatus::Active)
+            }
+        }
+    }
+
+    async fn resume(&self, id: &str) -> Result<bool, ApiError> {
+        let mut map = self.sessions.lock().await;
+        Ok(map.get_mut(id).map(|s| s.last_activity = Utc::now()).is_some())
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), ApiError> {
+        self.sessions.lock().await.remove(id);
+        Ok(())
+    }
+
+    async fn remove_user(&self, user_id: i32) -> Result<(), ApiError> {
+        self.sessions.lock().await.retain(|_, s| s.user_id != user_id);
+        Ok(())
+    }
+
+    async fn purge_stale(&self, max_idle_secs: i64) -> Result<u64, ApiError> {
+        let mut map = self.sessions.lock().await;
+        let now = Utc::now();
+        let before = map.len();
+        map.retain(|_, s| (now - s.last_activity).num_seconds() <= max_idle_secs);
+        Ok((before - map.len()) as u64)
+    }
+}
+
+/// Backend Postgres (bảng `sessions`) — dùng chung giữa nhiều replica
+pub struct PgSessionStore {
+    pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        PgSessionStore { pool }
+    }
+}
+
+#[async_trait]
+impl SessionStore for PgSessionStore {
+    async fn create(&self, id: &str, user_id: i32, ip: Option<&str>, user_agent: Option<&str>) -> Result<(), ApiError> {
+        db::create_session(&self.pool, id, user_id, ip, user_agent).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Session>, ApiError> {
+        db::get_session(&self.pool, id).await
+    }
+
+    async fn list(&self, user_id: i32) -> Result<Vec<Session>, ApiError> {
+        db::list_user_sessions(&self.pool, user_id).await
+    }
+
+    async fn touch(&self, id: &str, timeout_secs: i64) -> Result<SessionStatus, ApiError> {
+        Ok(match db::touch_session(&self.pool, id, timeout_secs).await? {
+            Some(true) => SessionStatus::Active,
+            Some(false) => SessionStatus::Idle,
+            None => SessionStatus::Missing,
+        })
+    }
+
+    async fn resume(&self, id: &str) -> Result<bool, ApiError> {
+        db::resume_session(&self.pool, id).await
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), ApiError> {
+        db::delete_session(&self.pool, id).await.map(|_| ())
+    }
+
+    async fn remove_user(&self, user_id: i32) -> Result<(), ApiError> {
+        db::delete_user_sessions(&self.pool, user_id).await.map(|_| ())
+    }
+
+    async fn purge_stale(&self, max_idle_secs: i64) -> Result<u64, ApiError> {
+        db::purge_stale_sessions(&self.pool, max_idle_secs).await
+    }
+}
+
+static STORE: OnceLock<Arc<dyn SessionStore>> = OnceLock::new();
+
+/// Khởi tạo session store theo cấu hình SESSION_STORE (gọi một lần trong main)
+pub fn init(pool: PgPool) -> anyhow::Result<()> {
+    let store: Arc<dyn SessionStore> = match CONFIG.session_store.as_str() {
+        "memory" => Arc::new(MemorySessionStore::default()),
+        "postgres" => Arc::new(PgSessionStore::new(pool)),
+        other => anyhow::bail!("Unknown SESSION_STORE: {}", other),
+    };
+    STORE
+        .set(store)
+        .map_err(|_| anyhow::anyhow!("Session store already initialized"))
+}
+
+/// Session store đang dùng
+pub fn store() -> Arc<dyn SessionStore> {
+    STORE.get().expect("session store not initialized").clone()
+}
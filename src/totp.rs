@@ -0,0 +1,173 @@
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand_core::{OsRng, RngCore};
+use ring::hmac;
+
+/// Độ dài time step (giây) và số chữ số của mã, theo mặc định của RFC 6238
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+
+/// Chấp nhận lệch ±1 step để bù sai lệch đồng hồ
+const SKEW_STEPS: i64 = 1;
+
+/// Thời hạn của challenge "mfa_required" (giây) và số lần nhập sai tối đa
+pub const CHALLENGE_TTL_SECS: i64 = 300;
+pub const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// Số mã khôi phục cấp khi bật 2FA
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Sinh secret ngẫu nhiên 160 bit, mã hóa base32 (không padding) cho app authenticator
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// URI `otpauth://` để app authenticator quét (thường hiển thị dạng QR)
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer_enc = utf8_percent_encode(issuer, NON_ALPHANUMERIC);
+    let account_enc = utf8_percent_encode(account, NON_ALPHANUMERIC);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer_enc, account_enc, secret, issuer_enc, DIGITS, STEP_SECS
+    )
+}
+
+/// Kiểm tra mã TOTP, trả về time step khớp (để chặn dùng lại mã cũ).
+/// Chỉ chấp nhận step lớn hơn `last_step`.
+pub fn verify(secret: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    verify_at(secret, code, last_step, Utc::now().timestamp())
+}
+
+fn verify_at(secret: &str, code: &str, last_step: Option<i64>, now_secs: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let current = now_secs / STEP_SECS;
+    (current - SKEW_STEPS..=current + SKEW_STEPS)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&key, *step as u64, DIGITS) == code)
+}
+
+/// HOTP (RFC 4226): HMAC-SHA1 của counter rồi dynamic truncation
+fn hotp(key: &[u8], counter: u64, digits: u32) -> u32 {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key);
+    let tag = hmac::sign(&key, &counter.to_be_bytes());
+    let digest = tag.as_ref();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(digits)
+}
+
+/// Sinh mã khôi phục dạng `xxxx-xxxx-xxxx-xxxx` (hex, 64 bit)
+pub fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+        .as_bytes()
+        .chunks(4)
+        .map(|c| std::str::from_utf8(c).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Chuẩn hóa mã khôi phục trước khi hash (bỏ dấu gạch, chữ thường)
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret dùng trong test vector của RFC 4226 và RFC 6238 (SHA-1)
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    fn code_at(secret: &str, step: i64) -> String {
+        let key = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        format!("{:06}", hotp(&key, step as u64, DIGITS))
+    }
+
+    #[test]
+    fn hotp_matches_rfc4226_appendix_d() {
+        let expected = [
+            755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(RFC_KEY, counter as u64, 6), *code, "counter {}", counter);
+        }
+    }
+
+    #[test]
+    fn totp_matches_rfc6238_sha1_vectors() {
+        let expected = [
+            (59, 94287082),
+            (1111111109, 7081804),
+            (1111111111, 14050471),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+            (20000000000, 65353130),
+        ];
+        for (time, code) in expected {
+            assert_eq!(hotp(RFC_KEY, (time / STEP_SECS) as u64, 8), code, "time {}", time);
+        }
+    }
+
+    #[test]
+    fn verify_accepts_current_step() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+        let now = 1111111111;
+        let step = now / STEP_SECS;
+        assert_eq!(verify_at(&secret, &code_at(&secret, step), None, now), Some(step));
+        assert_eq!(verify_at(&secret, &format!(" {} ", code_at(&secret, step)), None, now), Some(step));
+    }
+
+    #[test]
+    fn verify_allows_one_step_of_skew() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+        let now = 1111111111;
+        let step = now / STEP_SECS;
+        assert_eq!(verify_at(&secret, &code_at(&secret, step - 1), None, now), Some(step - 1));
+        assert_eq!(verify_at(&secret, &code_at(&secret, step + 1), None, now), Some(step + 1));
+        assert_eq!(verify_at(&secret, &code_at(&secret, step - 2), None, now), None);
+        assert_eq!(verify_at(&secret, &code_at(&secret, step + 2), None, now), None);
+    }
+
+    #[test]
+    fn verify_rejects_replayed_steps() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+        let now = 1111111111;
+        let step = now / STEP_SECS;
+        let code = code_at(&secret, step);
+        assert_eq!(verify_at(&secret, &code, Some(step), now), None);
+        assert_eq!(verify_at(&secret, &code, Some(step + 1), now), None);
+        assert_eq!(verify_at(&secret, &code, Some(step - 1), now), Some(step));
+        // Mã của step cũ hơn last_step cũng bị từ chối dù vẫn nằm trong khoảng lệch
+        assert_eq!(verify_at(&secret, &code_at(&secret, step - 1), Some(step - 1), now), None);
+    }
+
+    #[test]
+    fn verify_rejects_malformed_codes() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+        let now = 1111111111;
+        assert_eq!(verify_at(&secret, "12345", None, now), None);
+        assert_eq!(verify_at(&secret, "1234567", None, now), None);
+        assert_eq!(verify_at(&secret, "12a456", None, now), None);
+        assert_eq!(verify_at("not base32!", "123456", None, now), None);
+    }
+}